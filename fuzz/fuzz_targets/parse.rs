@@ -0,0 +1,29 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate motor;
+
+use motor::binary::Module;
+
+// Parses arbitrary bytes and walks everything the module exposes. None of
+// it may panic, whatever the input.
+fuzz_target!(|data: &[u8]| {
+    let module = match Module::parse_bytes(data) {
+        Ok(module) => module,
+        Err(_) => return,
+    };
+    module.import_types();
+    module.export_types();
+    module.required_features();
+    let _ = module.producers();
+    let _ = module.function_names();
+    module.entry_func_index();
+    let mut idx = 0;
+    while let Some(_) = module.func_type(idx) {
+        module.func_type_id(idx);
+        if let Some(body) = module.find_func(idx as usize) {
+            body.code();
+        }
+        idx += 1;
+    }
+});
@@ -0,0 +1,21 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate motor;
+
+use motor::binary::Module;
+
+// Any module that parses must encode to bytes that parse again and encode
+// to the same bytes.
+fuzz_target!(|data: &[u8]| {
+    let module = match Module::parse_bytes(data) {
+        Ok(module) => module,
+        Err(_) => return,
+    };
+    let mut encoded = vec![];
+    module.write(&mut encoded).unwrap();
+    let reparsed = Module::parse_bytes(&encoded).expect("encoded module doesn't parse");
+    let mut reencoded = vec![];
+    reparsed.write(&mut reencoded).unwrap();
+    assert_eq!(encoded, reencoded);
+});
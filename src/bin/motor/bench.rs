@@ -0,0 +1,65 @@
+use clap::ArgMatches;
+use dynasmrt::x64::Assembler;
+use dynasmrt::DynasmApi;
+use motor::binary::Module;
+use names::FuncNames;
+use std::fs::File;
+use std::io::Read;
+use std::mem;
+use std::time::{Duration, Instant};
+use {check_features, check_imports, compile_func, entry_func_index, find_body, parse_module};
+
+fn micros(d: Duration) -> f64 {
+    d.as_secs() as f64 * 1e6 + d.subsec_nanos() as f64 / 1e3
+}
+
+fn print_phase(name: &str, samples: &[Duration]) {
+    let min = micros(*samples.iter().min().unwrap());
+    let max = micros(*samples.iter().max().unwrap());
+    let mean = samples.iter().cloned().map(micros).sum::<f64>() / samples.len() as f64;
+    println!(
+        "{:<8} min {:>10.1}us  mean {:>10.1}us  max {:>10.1}us",
+        name, min, mean, max
+    );
+}
+
+// Measures parsing, compiling, and calling a function separately, repeating
+// each phase the requested number of times.
+pub fn bench(matches: &ArgMatches) {
+    let input = matches.value_of("input").unwrap();
+    let iterations: usize = matches.value_of("iterations").unwrap().parse().unwrap();
+    let module = parse_module(input);
+    check_imports(&module, matches);
+    check_features(&module);
+    let func_idx = entry_func_index(&module, matches.value_of("invoke"));
+    let func_desc = FuncNames::new(&module).describe(&module, func_idx);
+    let func = find_body(&module, func_idx, &func_desc);
+    let mut bytes = vec![];
+    File::open(input)
+        .and_then(|mut f| f.read_to_end(&mut bytes))
+        .expect("unable to read module");
+    let mut parse = vec![];
+    let mut compile = vec![];
+    let mut call = vec![];
+    for _ in 0..iterations {
+        let start = Instant::now();
+        Module::parse_bytes(&bytes).unwrap();
+        parse.push(start.elapsed());
+
+        let start = Instant::now();
+        let mut ops = Assembler::new();
+        let entry = ops.offset();
+        compile_func(&mut ops, func_idx, &func_desc, func, None);
+        let buf = ops.finalize().unwrap();
+        compile.push(start.elapsed());
+
+        let entry_fn: extern "C" fn() -> bool = unsafe { mem::transmute(buf.ptr(entry)) };
+        let start = Instant::now();
+        entry_fn();
+        call.push(start.elapsed());
+    }
+    println!("{}: {} iterations", func_desc, iterations);
+    print_phase("parse", &parse);
+    print_phase("compile", &compile);
+    print_phase("call", &call);
+}
@@ -0,0 +1,35 @@
+use clap::ArgMatches;
+use motor::binary::Module;
+
+// Post-MVP proposals and whether motor implements them. These are the
+// proposals Module::required_features() can detect, under the same names.
+const FEATURES: &[(&str, bool)] = &[
+    ("mutable-global", false),
+    ("multi-value", false),
+    ("reference-types", false),
+    ("bulk-memory", false),
+    ("threads", false),
+    ("simd", false),
+];
+
+pub fn is_supported(name: &str) -> bool {
+    FEATURES
+        .iter()
+        .any(|&(feature, supported)| feature == name && supported)
+}
+
+// Returns the features the module requires that motor does not implement.
+pub fn unsupported_features(module: &Module) -> Vec<&'static str> {
+    module
+        .required_features()
+        .into_iter()
+        .filter(|name| !is_supported(name))
+        .collect()
+}
+
+pub fn features(_matches: &ArgMatches) {
+    for &(name, supported) in FEATURES {
+        let status = if supported { "yes" } else { "no" };
+        println!("{:<26} {}", name, status);
+    }
+}
@@ -0,0 +1,216 @@
+use clap::ArgMatches;
+use motor::binary::{ElementType, ExternType, Module, ResizableLimits};
+use parse_module;
+
+fn extern_type_json(ty: &ExternType) -> String {
+    match *ty {
+        ExternType::Func(ty) => format!(
+            "{{\"kind\":\"func\",\"params\":{},\"results\":{}}}",
+            json_array(
+                ty.param_types()
+                    .iter()
+                    .map(|ty| json_string(&ty.to_string()))
+            ),
+            json_array(
+                ty.return_types()
+                    .iter()
+                    .map(|ty| json_string(&ty.to_string()))
+            )
+        ),
+        ExternType::Table(ty) => format!(
+            "{{\"kind\":\"table\",\"element\":{},{}}}",
+            json_string(element_type_name(ty.element_type())),
+            limits_json(ty.limits())
+        ),
+        ExternType::Memory(ty) => format!(
+            "{{\"kind\":\"memory\",{},\"shared\":{}}}",
+            limits_json(ty.limits()),
+            ty.limits().is_shared()
+        ),
+        ExternType::Global(ty) => format!(
+            "{{\"kind\":\"global\",\"type\":{},\"mutable\":{}}}",
+            json_string(&ty.content_type().to_string()),
+            ty.is_mutable()
+        ),
+    }
+}
+
+fn extern_type_text(ty: &ExternType) -> String {
+    match *ty {
+        ExternType::Func(ty) => format!("func {}", ty),
+        ExternType::Table(ty) => format!(
+            "table {} {}",
+            element_type_name(ty.element_type()),
+            limits_text(ty.limits())
+        ),
+        ExternType::Memory(ty) => format!("memory {}", limits_text(ty.limits())),
+        ExternType::Global(ty) => {
+            let mutability = if ty.is_mutable() { "mut " } else { "" };
+            format!("global {}{}", mutability, ty.content_type())
+        }
+    }
+}
+
+fn element_type_name(ty: ElementType) -> &'static str {
+    match ty {
+        ElementType::AnyFunc => "funcref",
+        ElementType::ExternRef => "externref",
+    }
+}
+
+fn limits_json(limits: &ResizableLimits) -> String {
+    let maximum = match limits.maximum() {
+        Some(maximum) => maximum.to_string(),
+        None => "null".to_string(),
+    };
+    format!("\"initial\":{},\"maximum\":{}", limits.initial(), maximum)
+}
+
+fn limits_text(limits: &ResizableLimits) -> String {
+    let mut text = match limits.maximum() {
+        Some(maximum) => format!("{}..{}", limits.initial(), maximum),
+        None => format!("{}..", limits.initial()),
+    };
+    if limits.is_shared() {
+        text.push_str(" shared");
+    }
+    text
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_array<I: Iterator<Item = String>>(items: I) -> String {
+    let items: Vec<String> = items.collect();
+    format!("[{}]", items.join(","))
+}
+
+fn print_json(module: &Module) {
+    let imports = json_array(module.import_types().into_iter().map(|(module, name, ty)| {
+        format!(
+            "{{\"module\":{},\"name\":{},\"type\":{}}}",
+            json_string(module),
+            json_string(name),
+            ty.map_or("null".to_string(), |ty| extern_type_json(&ty))
+        )
+    }));
+    let exports = json_array(module.export_types().into_iter().map(|(name, ty)| {
+        format!(
+            "{{\"name\":{},\"type\":{}}}",
+            json_string(name),
+            ty.map_or("null".to_string(), |ty| extern_type_json(&ty))
+        )
+    }));
+    let tables = json_array(
+        (0..)
+            .map(|idx| module.table_type(idx))
+            .take_while(|ty| ty.is_some())
+            .map(|ty| extern_type_json(&ExternType::Table(ty.unwrap()))),
+    );
+    let memories = json_array(
+        (0..)
+            .map(|idx| module.memory_type(idx))
+            .take_while(|ty| ty.is_some())
+            .map(|ty| extern_type_json(&ExternType::Memory(ty.unwrap()))),
+    );
+    let start = match module.start_func_index() {
+        Some(idx) => idx.to_string(),
+        None => "null".to_string(),
+    };
+    let features = json_array(module.required_features().into_iter().map(json_string));
+    let producers = match module.producers() {
+        Ok(fields) => {
+            let fields: Vec<String> = fields
+                .iter()
+                .map(|field| {
+                    let values = json_array(field.values().iter().map(|value| {
+                        format!(
+                            "{{\"name\":{},\"version\":{}}}",
+                            json_string(value.name()),
+                            json_string(value.version())
+                        )
+                    }));
+                    format!("{}:{}", json_string(field.name()), values)
+                })
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        }
+        Err(e) => {
+            warn!("malformed producers section: {:?}", e);
+            "null".to_string()
+        }
+    };
+    println!(
+        "{{\"imports\":{},\"exports\":{},\"tables\":{},\"memories\":{},\"start\":{},\"features\":{},\"producers\":{}}}",
+        imports, exports, tables, memories, start, features, producers
+    );
+}
+
+fn print_text(module: &Module) {
+    for (module, name, ty) in module.import_types() {
+        let ty = ty.map_or("<invalid>".to_string(), |ty| extern_type_text(&ty));
+        println!("import {}.{}: {}", module, name, ty);
+    }
+    for (name, ty) in module.export_types() {
+        let ty = ty.map_or("<invalid>".to_string(), |ty| extern_type_text(&ty));
+        println!("export {}: {}", name, ty);
+    }
+    let mut idx = 0;
+    while let Some(ty) = module.table_type(idx) {
+        println!(
+            "table[{}]: {} {}",
+            idx,
+            element_type_name(ty.element_type()),
+            limits_text(ty.limits())
+        );
+        idx += 1;
+    }
+    let mut idx = 0;
+    while let Some(ty) = module.memory_type(idx) {
+        println!("memory[{}]: {}", idx, limits_text(ty.limits()));
+        idx += 1;
+    }
+    if let Some(idx) = module.start_func_index() {
+        println!("start: func[{}]", idx);
+    }
+    let features = module.required_features();
+    if !features.is_empty() {
+        println!("features: {}", features.join(", "));
+    }
+    match module.producers() {
+        Ok(fields) => {
+            for field in fields {
+                let values: Vec<String> = field
+                    .values()
+                    .iter()
+                    .map(|value| format!("{} {}", value.name(), value.version()))
+                    .collect();
+                println!("producer {}: {}", field.name(), values.join(", "));
+            }
+        }
+        Err(e) => warn!("malformed producers section: {:?}", e),
+    }
+}
+
+pub fn inspect(matches: &ArgMatches) {
+    let module = parse_module(matches.value_of("input").unwrap());
+    if matches.is_present("json") {
+        print_json(&module);
+    } else {
+        print_text(&module);
+    }
+}
@@ -0,0 +1,1021 @@
+#![feature(plugin)]
+#![plugin(dynasm)]
+
+#[cfg(feature = "emit-asm")]
+extern crate capstone;
+extern crate clap;
+extern crate dynasmrt;
+extern crate env_logger;
+#[macro_use]
+extern crate log;
+extern crate motor;
+
+mod bench;
+mod features;
+mod inspect;
+mod names;
+mod policy;
+mod sandbox;
+
+#[cfg(feature = "emit-asm")]
+use capstone::prelude::*;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use dynasmrt::x64::Assembler;
+use dynasmrt::{AssemblyOffset, DynasmApi, ExecutableBuffer};
+use motor::binary::{ExternalKind, FunctionBody, Module};
+use motor::opcode;
+use motor::opcode::*;
+use names::FuncNames;
+use policy::ImportPolicy;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::mem;
+use std::process::{self, Command};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+// Default size of the stack that WebAssembly code executes on.
+const DEFAULT_STACK_SIZE: usize = 8 << 20;
+
+// Exit status when --timeout expires, same as timeout(1).
+const TIMEOUT_EXIT_STATUS: i32 = 124;
+
+// How often --watch checks the input file for changes.
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+
+// Callbacks invoked by instrumented JIT code.
+trait Hooks {
+    fn call(&mut self, func_idx: u32);
+    fn ret(&mut self, func_idx: u32);
+    fn insn(&mut self, func_idx: u32, offset: u32, insn: u8);
+}
+
+// Instrumentation settings for the JIT. The hooks are passed to generated
+// code as a thin pointer to the trait object reference.
+#[derive(Clone, Copy)]
+struct Instrument<'a> {
+    hooks: *mut &'a mut dyn Hooks,
+    insns: bool,
+}
+
+extern "C" fn hook_call(hooks: *mut &mut dyn Hooks, func_idx: u32) {
+    unsafe { (*hooks).call(func_idx) }
+}
+
+extern "C" fn hook_return(hooks: *mut &mut dyn Hooks, func_idx: u32) {
+    unsafe { (*hooks).ret(func_idx) }
+}
+
+extern "C" fn hook_insn(hooks: *mut &mut dyn Hooks, func_idx: u32, offset: u32, insn: u32) {
+    unsafe { (*hooks).insn(func_idx, offset, insn as u8) }
+}
+
+struct Tracer {
+    out: Box<dyn Write>,
+    names: FuncNames,
+}
+
+impl Hooks for Tracer {
+    fn call(&mut self, func_idx: u32) {
+        let _ = writeln!(self.out, "call {}", self.names.name(func_idx));
+    }
+
+    fn ret(&mut self, func_idx: u32) {
+        let _ = writeln!(self.out, "return {}", self.names.name(func_idx));
+    }
+
+    fn insn(&mut self, func_idx: u32, offset: u32, insn: u8) {
+        let func_name = self.names.name(func_idx);
+        let _ = match opcode::name(insn) {
+            Some(name) => writeln!(self.out, "{}+{:#x}: {}", func_name, offset, name),
+            None => writeln!(self.out, "{}+{:#x}: {:#04x}", func_name, offset, insn),
+        };
+    }
+}
+
+// Counts executed instructions per call stack.
+struct Profiler {
+    stack: Vec<u32>,
+    counts: BTreeMap<Vec<u32>, u64>,
+}
+
+impl Hooks for Profiler {
+    fn call(&mut self, func_idx: u32) {
+        self.stack.push(func_idx);
+    }
+
+    fn ret(&mut self, _func_idx: u32) {
+        self.stack.pop();
+    }
+
+    fn insn(&mut self, _func_idx: u32, _offset: u32, _insn: u8) {
+        *self.counts.entry(self.stack.clone()).or_insert(0) += 1;
+    }
+}
+
+impl Profiler {
+    // Writes the profile in the collapsed stack format understood by
+    // flamegraph tools: one line per stack with frames separated by ';'.
+    fn write_collapsed(&self, module: &Module, out: &mut dyn Write) -> io::Result<()> {
+        let names = FuncNames::new(module);
+        for (stack, count) in &self.counts {
+            let frames: Vec<String> = stack.iter().map(|idx| names.name(*idx)).collect();
+            try!(writeln!(out, "{} {}", frames.join(";"), count));
+        }
+        Ok(())
+    }
+}
+
+// Records how many times each function was called.
+struct Coverage {
+    calls: BTreeMap<u32, u64>,
+}
+
+impl Hooks for Coverage {
+    fn call(&mut self, func_idx: u32) {
+        *self.calls.entry(func_idx).or_insert(0) += 1;
+    }
+
+    fn ret(&mut self, _func_idx: u32) {}
+
+    fn insn(&mut self, _func_idx: u32, _offset: u32, _insn: u8) {}
+}
+
+impl Coverage {
+    // Writes function coverage as an LCOV tracefile. Without debug info
+    // there are no source lines to map to, so functions are reported
+    // against the module itself with their index plus one standing in for
+    // a line, as LCOV lines start at 1. FNDA records refer to functions by
+    // name, so names that repeat get the function index appended.
+    fn write_lcov(&self, module: &Module, source: &str, out: &mut dyn Write) -> io::Result<()> {
+        try!(writeln!(out, "SF:{}", source));
+        let names = FuncNames::new(module);
+        let mut funcs: Vec<(u32, String)> = (0..)
+            .take_while(|idx| module.func_type(*idx).is_some())
+            .filter(|idx| module.find_func(*idx as usize).is_some())
+            .map(|idx| (idx, names.name(idx)))
+            .collect();
+        let mut name_counts: BTreeMap<String, usize> = BTreeMap::new();
+        for &(_, ref name) in &funcs {
+            *name_counts.entry(name.clone()).or_insert(0) += 1;
+        }
+        for &mut (idx, ref mut name) in &mut funcs {
+            if name_counts[name] > 1 {
+                *name = format!("{}[{}]", name, idx);
+            }
+        }
+        for &(idx, ref name) in &funcs {
+            try!(writeln!(out, "FN:{},{}", idx + 1, name));
+        }
+        for &(idx, ref name) in &funcs {
+            let count = self.calls.get(&idx).cloned().unwrap_or(0);
+            try!(writeln!(out, "FNDA:{},{}", count, name));
+        }
+        try!(writeln!(out, "FNF:{}", funcs.len()));
+        let hit = funcs
+            .iter()
+            .filter(|&&(idx, _)| self.calls.contains_key(&idx))
+            .count();
+        try!(writeln!(out, "FNH:{}", hit));
+        writeln!(out, "end_of_record")
+    }
+}
+
+// Emits a call to an instrumentation hook. Generated code runs with the
+// stack misaligned by the return address, so realign it around the call.
+fn emit_hook(ops: &mut Assembler, hook: *const (), hooks: *mut &mut dyn Hooks, args: &[u32]) {
+    dynasm!(ops
+        ; sub rsp, 8
+        ; mov rdi, QWORD hooks as i64
+    );
+    for (i, arg) in args.iter().enumerate() {
+        let arg = *arg as i32;
+        match i {
+            0 => dynasm!(ops ; mov esi, arg),
+            1 => dynasm!(ops ; mov edx, arg),
+            2 => dynasm!(ops ; mov ecx, arg),
+            _ => panic!("Too many hook arguments"),
+        }
+    }
+    dynasm!(ops
+        ; mov rax, QWORD hook as i64
+        ; call rax
+        ; add rsp, 8
+    );
+}
+
+// Compiles a function and returns the offset of the machine code generated
+// for each of its instructions, followed by the offset of the code for the
+// final end.
+fn compile_func(
+    ops: &mut Assembler,
+    func_idx: u32,
+    func_desc: &str,
+    func: &FunctionBody,
+    instrument: Option<Instrument>,
+) -> Vec<AssemblyOffset> {
+    let mut insn_offsets = vec![];
+    if let Some(instrument) = instrument {
+        emit_hook(ops, hook_call as *const (), instrument.hooks, &[func_idx]);
+    }
+    for (offset, insn) in func.code().iter().enumerate() {
+        insn_offsets.push(ops.offset());
+        if let Some(Instrument { hooks, insns: true }) = instrument {
+            let args = [func_idx, offset as u32, *insn as u32];
+            emit_hook(ops, hook_insn as *const (), hooks, &args);
+        }
+        match *insn {
+            OPC_RETURN => {
+                if let Some(instrument) = instrument {
+                    emit_hook(ops, hook_return as *const (), instrument.hooks, &[func_idx]);
+                }
+                dynasm!(ops
+                    ; ret
+                );
+            }
+            _ => panic!(
+                "Unsupported instruction {:#04x} in {} at offset {:#x}",
+                insn, func_desc, offset
+            ),
+        }
+    }
+    // The body's final end opcode isn't part of its code, but falling off
+    // the end of a function returns from it.
+    insn_offsets.push(ops.offset());
+    if let Some(instrument) = instrument {
+        emit_hook(ops, hook_return as *const (), instrument.hooks, &[func_idx]);
+    }
+    dynasm!(ops
+        ; ret
+    );
+    insn_offsets
+}
+
+// Prints the machine code of a function with each WebAssembly instruction
+// as a comment before the code generated for it.
+#[cfg(feature = "emit-asm")]
+fn disassemble(
+    buf: &ExecutableBuffer,
+    func_desc: &str,
+    func: &FunctionBody,
+    entry: AssemblyOffset,
+    insn_offsets: &[AssemblyOffset],
+) {
+    let mut cs = Capstone::new()
+        .x86()
+        .mode(arch::x86::ArchMode::Mode64)
+        .syntax(arch::x86::ArchSyntax::Intel)
+        .build()
+        .unwrap();
+    let mut print_range = |start: usize, end: usize| {
+        let insns = cs.disasm_all(&buf[start..end], start as u64).unwrap();
+        for insn in insns.iter() {
+            let text = format!(
+                "{} {}",
+                insn.mnemonic().unwrap_or(""),
+                insn.op_str().unwrap_or("")
+            );
+            println!("  {:04x}: {}", insn.address(), text.trim_end());
+        }
+    };
+    println!("{}:", func_desc);
+    print_range(entry.0, insn_offsets[0].0);
+    for (i, insn) in func.code().iter().enumerate() {
+        match opcode::name(*insn) {
+            Some(name) => println!("  ; {}", name),
+            None => println!("  ; {:#04x}", insn),
+        }
+        print_range(insn_offsets[i].0, insn_offsets[i + 1].0);
+    }
+    println!("  ; end");
+    print_range(insn_offsets[func.code().len()].0, buf.len());
+}
+
+#[cfg(not(feature = "emit-asm"))]
+fn disassemble(
+    _buf: &ExecutableBuffer,
+    _func_desc: &str,
+    _func: &FunctionBody,
+    _entry: AssemblyOffset,
+    _insn_offsets: &[AssemblyOffset],
+) {
+    error!("--emit-asm requires motor to be built with the emit-asm feature");
+    process::exit(1);
+}
+
+fn find_body<'a>(module: &'a Module, func_idx: u32, func_desc: &str) -> &'a FunctionBody {
+    match module.find_func(func_idx as usize) {
+        Some(func) => func,
+        None => {
+            error!("{} is imported and cannot be run directly", func_desc);
+            process::exit(1);
+        }
+    }
+}
+
+fn execute(
+    module: &Module,
+    func_idx: u32,
+    instrument: Option<Instrument>,
+    emit_asm: bool,
+    sandbox: bool,
+) {
+    let func_desc = FuncNames::new(module).describe(module, func_idx);
+    let func = find_body(module, func_idx, &func_desc);
+    let mut ops = Assembler::new();
+    let entry = ops.offset();
+    let insn_offsets = compile_func(&mut ops, func_idx, &func_desc, func, instrument);
+    let buf = ops.finalize().unwrap();
+    debug!(
+        "compiled {} to {} bytes of machine code",
+        func_desc,
+        buf.len()
+    );
+    if emit_asm {
+        disassemble(&buf, &func_desc, func, entry, &insn_offsets);
+    }
+    if sandbox {
+        if let Err(e) = sandbox::enter() {
+            error!("unable to enter sandbox: {}", e);
+            process::exit(1);
+        }
+        debug!("entered seccomp sandbox");
+    }
+    let entry_fn: extern "C" fn() -> bool = unsafe { mem::transmute(buf.ptr(entry)) };
+    info!("invoking {}", func_desc);
+    entry_fn();
+    info!("{} returned", func_desc);
+}
+
+fn parse_module(filename: &str) -> Module {
+    let mut f = match File::open(filename) {
+        Ok(f) => f,
+        Err(e) => {
+            error!("unable to open {}: {}", filename, e);
+            process::exit(1);
+        }
+    };
+    info!("parsing {}", filename);
+    match Module::parse(&mut f) {
+        Ok(module) => module,
+        Err(e) => {
+            error!("failed to parse {}: {:?}", filename, e);
+            process::exit(1);
+        }
+    }
+}
+
+fn create_file(path: &str) -> File {
+    match File::create(path) {
+        Ok(f) => f,
+        Err(e) => {
+            error!("unable to create {}: {}", path, e);
+            process::exit(1);
+        }
+    }
+}
+
+// Exits if writing an output file failed.
+fn check_write(path: &str, result: io::Result<()>) {
+    if let Err(e) = result {
+        error!("unable to write {}: {}", path, e);
+        process::exit(1);
+    }
+}
+
+fn check_imports(module: &Module, matches: &ArgMatches) {
+    let refused = ImportPolicy::from_matches(matches).refused_imports(module);
+    if !refused.is_empty() {
+        error!(
+            "module requests imports outside the allowed set: {}",
+            refused.join(", ")
+        );
+        process::exit(1);
+    }
+}
+
+// Refuses modules that need proposals the JIT doesn't implement, naming
+// them instead of failing later on an unsupported instruction.
+fn check_features(module: &Module) {
+    let unsupported = features::unsupported_features(module);
+    if !unsupported.is_empty() {
+        error!(
+            "module requires unsupported features: {} (see `motor features`)",
+            unsupported.join(", ")
+        );
+        process::exit(1);
+    }
+}
+
+// Picks the function to run: the exported function named by --invoke, or
+// the module's entry point. Exits with the list of exported functions
+// when there is nothing to run.
+fn entry_func_index(module: &Module, invoke: Option<&str>) -> u32 {
+    let func_idx = match invoke {
+        Some(name) => module.find_export_func_index(name),
+        None => module.entry_func_index(),
+    };
+    match func_idx {
+        Some(idx) => idx,
+        None => {
+            match invoke {
+                Some(name) => error!("no exported function named '{}'", name),
+                None => error!("no start function and no exported '_start' or 'main' function"),
+            }
+            let names: Vec<&str> = module
+                .exports()
+                .iter()
+                .filter(|entry| entry.kind() == ExternalKind::Function)
+                .map(|entry| entry.field_name())
+                .collect();
+            if names.is_empty() {
+                error!("module exports no functions");
+            } else {
+                error!("exported functions: {}", names.join(", "));
+            }
+            process::exit(1);
+        }
+    }
+}
+
+fn init_logging(verbosity: u64) {
+    let default_filter = match verbosity {
+        0 => "warn",
+        1 => "info",
+        2 => "debug",
+        _ => "trace",
+    };
+    let env = env_logger::Env::new().filter_or("MOTOR_LOG", default_filter);
+    env_logger::Builder::from_env(env).init();
+}
+
+// Parses a byte size with an optional K, M, or G suffix.
+fn parse_size(s: &str) -> Option<usize> {
+    let (digits, shift) = match s.chars().last() {
+        Some('K') | Some('k') => (&s[..s.len() - 1], 10),
+        Some('M') | Some('m') => (&s[..s.len() - 1], 20),
+        Some('G') | Some('g') => (&s[..s.len() - 1], 30),
+        _ => (s, 0),
+    };
+    match digits.parse::<usize>() {
+        Ok(n) => n.checked_mul(1 << shift),
+        Err(_) => None,
+    }
+}
+
+// Parses a duration with an optional ms, s, m, or h suffix. A number
+// without a suffix is in seconds.
+fn parse_duration(s: &str) -> Option<Duration> {
+    let (digits, millis) = if s.ends_with("ms") {
+        (&s[..s.len() - 2], 1)
+    } else if s.ends_with('s') {
+        (&s[..s.len() - 1], 1000)
+    } else if s.ends_with('m') {
+        (&s[..s.len() - 1], 60 * 1000)
+    } else if s.ends_with('h') {
+        (&s[..s.len() - 1], 60 * 60 * 1000)
+    } else {
+        (s, 1000)
+    };
+    match digits.parse::<u64>() {
+        Ok(n) => n.checked_mul(millis).map(Duration::from_millis),
+        Err(_) => None,
+    }
+}
+
+// Returns the --timeout duration, if any. A zero duration disables the
+// timeout, same as timeout(1).
+fn timeout_duration(s: &str) -> Option<Duration> {
+    parse_duration(s).filter(|&duration| duration != Duration::from_secs(0))
+}
+
+fn run(matches: &ArgMatches) {
+    let module = parse_module(matches.value_of("input").unwrap());
+    check_imports(&module, matches);
+    check_features(&module);
+    let mut tracer = if matches.is_present("trace") {
+        let out: Box<dyn Write> = match matches.value_of("trace-file") {
+            Some(path) => Box::new(BufWriter::new(create_file(path))),
+            None => Box::new(io::stderr()),
+        };
+        Some(Tracer {
+            out: out,
+            names: FuncNames::new(&module),
+        })
+    } else {
+        None
+    };
+    let mut hooks = tracer.as_mut().map(|tracer| tracer as &mut dyn Hooks);
+    let instrument = hooks.as_mut().map(|hooks| Instrument {
+        hooks: hooks as *mut &mut dyn Hooks,
+        insns: matches.value_of("trace") != Some("calls"),
+    });
+    let func_idx = entry_func_index(&module, None);
+    execute(
+        &module,
+        func_idx,
+        instrument,
+        matches.is_present("emit-asm"),
+        matches.is_present("sandbox"),
+    );
+}
+
+fn modified_time(filename: &str) -> Option<SystemTime> {
+    fs::metadata(filename)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+// Runs the program with `motor run` in a child process and runs it again
+// every time the input file changes. A child process keeps the watcher alive
+// when the program fails to parse, exits, or crashes in generated code.
+fn watch(filename: &str, args: &[String]) -> ! {
+    let exe = env::current_exe().expect("unable to locate motor executable");
+    let args: Vec<&String> = args
+        .iter()
+        .skip(1)
+        .filter(|arg| *arg != "--watch")
+        .collect();
+    loop {
+        let modified = modified_time(filename);
+        info!("running {}", filename);
+        match Command::new(&exe).args(&args).status() {
+            Ok(ref status) if status.success() => {}
+            Ok(status) => warn!("{} failed: {}", filename, status),
+            Err(e) => error!("unable to run {}: {}", filename, e),
+        }
+        info!("waiting for {} to change", filename);
+        while modified_time(filename) == modified || modified_time(filename).is_none() {
+            thread::sleep(WATCH_INTERVAL);
+        }
+    }
+}
+
+fn profile(matches: &ArgMatches) {
+    let module = parse_module(matches.value_of("input").unwrap());
+    check_imports(&module, matches);
+    check_features(&module);
+    let func_idx = entry_func_index(&module, matches.value_of("invoke"));
+    // Open the output first so that a bad path doesn't waste a whole run.
+    let output = matches.value_of("output").unwrap();
+    let mut out = BufWriter::new(create_file(output));
+    let mut profiler = Profiler {
+        stack: vec![],
+        counts: BTreeMap::new(),
+    };
+    {
+        let mut hooks: &mut dyn Hooks = &mut profiler;
+        let instrument = Instrument {
+            hooks: &mut hooks as *mut &mut dyn Hooks,
+            insns: true,
+        };
+        execute(&module, func_idx, Some(instrument), false, false);
+    }
+    let result = profiler.write_collapsed(&module, &mut out);
+    check_write(output, result.and_then(|_| out.flush()));
+}
+
+fn coverage(matches: &ArgMatches) {
+    let input = matches.value_of("input").unwrap();
+    let module = parse_module(input);
+    check_imports(&module, matches);
+    check_features(&module);
+    let func_idx = entry_func_index(&module, matches.value_of("invoke"));
+    let output = matches.value_of("output").unwrap();
+    let mut out = BufWriter::new(create_file(output));
+    let mut coverage = Coverage {
+        calls: BTreeMap::new(),
+    };
+    {
+        let mut hooks: &mut dyn Hooks = &mut coverage;
+        let instrument = Instrument {
+            hooks: &mut hooks as *mut &mut dyn Hooks,
+            insns: false,
+        };
+        execute(&module, func_idx, Some(instrument), false, false);
+    }
+    let result = coverage.write_lcov(&module, input, &mut out);
+    check_write(output, result.and_then(|_| out.flush()));
+}
+
+fn strip(matches: &ArgMatches) {
+    let mut module = parse_module(matches.value_of("input").unwrap());
+    let keep_names = matches.is_present("keep-names");
+    module.retain_custom_sections(|name| keep_names && name == "name");
+    let output = matches.value_of("output").unwrap();
+    let mut out = BufWriter::new(create_file(output));
+    let result = module.write(&mut out);
+    check_write(output, result.and_then(|_| out.flush()));
+}
+
+// Subcommands other than run. Anything else in their place is taken as the
+// input of `motor FILE`, which is short for `motor run FILE`.
+const SUBCOMMANDS: &[&str] = &[
+    "inspect", "profile", "coverage", "bench", "features", "strip",
+];
+
+fn app<'a, 'b>() -> App<'a, 'b> {
+    App::new("Motor")
+        .version("0.1")
+        .author("Pekka Enberg <penberg@iki.fi>")
+        .about("Motor is a runtime for executing WebAssembly programs")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .setting(AppSettings::AllowExternalSubcommands)
+        .after_help("`motor FILE` is short for `motor run FILE`.")
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .multiple(true)
+                .global(true)
+                .help("Increase logging verbosity (overridden by MOTOR_LOG)"),
+        )
+        .arg(
+            Arg::with_name("stack-size")
+                .long("stack-size")
+                .help("Size of the stack WebAssembly code runs on (default: 8M)")
+                .takes_value(true)
+                .value_name("SIZE")
+                .global(true)
+                .validator(|s| match parse_size(&s) {
+                    Some(_) => Ok(()),
+                    None => Err(format!("invalid stack size '{}'", s)),
+                }),
+        )
+        .arg(
+            Arg::with_name("timeout")
+                .long("timeout")
+                .help("Stop the program if it runs longer than DURATION, e.g. 5s; 0 disables the timeout")
+                .takes_value(true)
+                .value_name("DURATION")
+                .global(true)
+                .validator(|s| match parse_duration(&s) {
+                    Some(_) => Ok(()),
+                    None => Err(format!("invalid timeout '{}'", s)),
+                }),
+        )
+        .arg(
+            Arg::with_name("allow-import")
+                .long("allow-import")
+                .help("Only allow imports matching PATTERN, e.g. 'env.*'")
+                .takes_value(true)
+                .value_name("PATTERN")
+                .multiple(true)
+                .number_of_values(1)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("deny-import")
+                .long("deny-import")
+                .help("Refuse imports matching PATTERN, even if allowed")
+                .takes_value(true)
+                .value_name("PATTERN")
+                .multiple(true)
+                .number_of_values(1)
+                .global(true),
+        )
+        .subcommand(
+            SubCommand::with_name("run")
+                .about("Runs a WebAssembly program (the default when given a file)")
+                .arg(
+                    Arg::with_name("input")
+                        .help("WebAssembly program to run")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("trace")
+                        .long("trace")
+                        .help("Trace execution of function calls or instructions")
+                        .takes_value(true)
+                        .min_values(0)
+                        .require_equals(true)
+                        .possible_values(&["calls", "instrs"]),
+                )
+                .arg(
+                    Arg::with_name("trace-file")
+                        .long("trace-file")
+                        .help("Write execution trace to a file instead of standard error")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .requires("trace"),
+                )
+                .arg(
+                    Arg::with_name("emit-asm")
+                        .long("emit-asm")
+                        .help("Print generated machine code (needs the emit-asm feature)"),
+                )
+                .arg(
+                    Arg::with_name("sandbox")
+                        .long("sandbox")
+                        .help("Restrict system calls with seccomp once code is generated"),
+                )
+                .arg(
+                    Arg::with_name("watch")
+                        .long("watch")
+                        .help("Run the program again whenever the input file changes"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("inspect")
+                .about("Prints information about a WebAssembly module")
+                .arg(
+                    Arg::with_name("input")
+                        .help("WebAssembly module to inspect")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("Print module information as JSON"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("profile")
+                .about("Profiles execution of a WebAssembly program")
+                .arg(
+                    Arg::with_name("input")
+                        .help("WebAssembly program to profile")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("invoke")
+                        .long("invoke")
+                        .help("Exported function to invoke instead of the start function")
+                        .takes_value(true)
+                        .value_name("FUNCTION"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .help("Write collapsed stacks to FILE")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .default_value("motor.folded"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("coverage")
+                .about("Records which functions a WebAssembly program executes")
+                .arg(
+                    Arg::with_name("input")
+                        .help("WebAssembly program to run")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("invoke")
+                        .long("invoke")
+                        .help("Exported function to invoke instead of the start function")
+                        .takes_value(true)
+                        .value_name("FUNCTION"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .help("Write an LCOV report to FILE")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .default_value("motor.info"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("bench")
+                .about("Measures parsing, compiling, and calling a WebAssembly program")
+                .arg(
+                    Arg::with_name("input")
+                        .help("WebAssembly program to measure")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("invoke")
+                        .long("invoke")
+                        .help("Exported function to call instead of the start function")
+                        .takes_value(true)
+                        .value_name("FUNCTION"),
+                )
+                .arg(
+                    Arg::with_name("iterations")
+                        .short("n")
+                        .long("iterations")
+                        .help("Number of times to repeat each phase")
+                        .takes_value(true)
+                        .value_name("N")
+                        .default_value("100")
+                        .validator(|s| match s.parse::<usize>() {
+                            Ok(n) if n > 0 => Ok(()),
+                            _ => Err(format!("invalid iteration count '{}'", s)),
+                        }),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("features")
+                .about("Lists WebAssembly proposals and whether they are supported"),
+        )
+        .subcommand(
+            SubCommand::with_name("strip")
+                .about("Removes custom sections from a WebAssembly module")
+                .arg(
+                    Arg::with_name("input")
+                        .help("WebAssembly module to strip")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .help("Write the stripped module to FILE")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("keep-names")
+                        .long("keep-names")
+                        .help("Keep the name section"),
+                ),
+        )
+}
+
+// Parses the command line, treating it as `motor run ...` if it doesn't
+// start with a subcommand. Returns the arguments as parsed.
+fn parse_args() -> (Vec<String>, ArgMatches<'static>) {
+    let args: Vec<String> = env::args().collect();
+    let error = match app().get_matches_from_safe(&args) {
+        Ok(matches) => match matches.subcommand_name() {
+            Some(name) if name != "run" && !SUBCOMMANDS.contains(&name) => None,
+            _ => return (args, matches),
+        },
+        // Options of run given before the input, e.g. `motor --trace FILE`.
+        Err(e) => match e.kind {
+            clap::ErrorKind::UnknownArgument => Some(e),
+            _ => e.exit(),
+        },
+    };
+    let mut run_args = args.clone();
+    run_args.insert(1, "run".to_string());
+    match app().get_matches_from_safe(&run_args) {
+        Ok(matches) => (run_args, matches),
+        Err(e) => error.unwrap_or(e).exit(),
+    }
+}
+
+fn main() {
+    let (args, matches) = parse_args();
+    let (verbosity, stack_size, timeout) = {
+        let global_matches = match matches.subcommand() {
+            (_, Some(matches)) => matches,
+            _ => &matches,
+        };
+        let stack_size = match global_matches.value_of("stack-size") {
+            Some(size) => (size.to_string(), parse_size(size).unwrap()),
+            None => ("8M".to_string(), DEFAULT_STACK_SIZE),
+        };
+        let timeout = global_matches.value_of("timeout").and_then(|timeout| {
+            timeout_duration(timeout).map(|duration| (timeout.to_string(), duration))
+        });
+        (
+            global_matches.occurrences_of("verbose"),
+            stack_size,
+            timeout,
+        )
+    };
+    init_logging(verbosity);
+    if let ("run", Some(run_matches)) = matches.subcommand() {
+        if run_matches.is_present("watch") {
+            watch(run_matches.value_of("input").unwrap(), &args);
+        }
+    }
+    // Run on a thread of our own so that the stack available to
+    // WebAssembly code doesn't depend on how the main thread was set up.
+    let (done_tx, done_rx) = mpsc::channel();
+    let child = thread::Builder::new()
+        .name("motor".to_string())
+        .stack_size(stack_size.1)
+        .spawn(move || {
+            match matches.subcommand() {
+                ("run", Some(matches)) => run(matches),
+                ("inspect", Some(matches)) => inspect::inspect(matches),
+                ("profile", Some(matches)) => profile(matches),
+                ("coverage", Some(matches)) => coverage(matches),
+                ("bench", Some(matches)) => bench::bench(matches),
+                ("features", Some(matches)) => features::features(matches),
+                ("strip", Some(matches)) => strip(matches),
+                _ => unreachable!(),
+            }
+            done_tx.send(()).unwrap();
+        });
+    let child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            error!("unable to allocate a stack of {}: {}", stack_size.0, e);
+            process::exit(1);
+        }
+    };
+    // Generated code cannot be interrupted, so give up on the thread
+    // running it and exit the whole process when the timeout expires.
+    if let Some((text, timeout)) = timeout {
+        if let Err(RecvTimeoutError::Timeout) = done_rx.recv_timeout(timeout) {
+            error!("timed out after {}", text);
+            process::exit(TIMEOUT_EXIT_STATUS);
+        }
+    }
+    if child.join().is_err() {
+        process::exit(101);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_size_suffixes() {
+        assert_eq!(parse_size("4096"), Some(4096));
+        assert_eq!(parse_size("64k"), Some(64 << 10));
+        assert_eq!(parse_size("8M"), Some(8 << 20));
+        assert_eq!(parse_size("1G"), Some(1 << 30));
+    }
+
+    #[test]
+    fn parse_size_invalid() {
+        assert_eq!(parse_size(""), None);
+        assert_eq!(parse_size("M"), None);
+        assert_eq!(parse_size("8T"), None);
+        assert_eq!(parse_size("-1"), None);
+        assert_eq!(parse_size(&format!("{}G", usize::max_value())), None);
+    }
+
+    #[test]
+    fn parse_duration_suffixes() {
+        assert_eq!(parse_duration("5"), Some(Duration::from_secs(5)));
+        assert_eq!(parse_duration("250ms"), Some(Duration::from_millis(250)));
+        assert_eq!(parse_duration("5s"), Some(Duration::from_secs(5)));
+        assert_eq!(parse_duration("2m"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_duration("1h"), Some(Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn parse_duration_invalid() {
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("ms"), None);
+        assert_eq!(parse_duration("1.5s"), None);
+        assert_eq!(parse_duration("5d"), None);
+        assert_eq!(parse_duration(&format!("{}h", u64::max_value())), None);
+    }
+
+    #[test]
+    fn timeout_zero_disables() {
+        assert_eq!(timeout_duration("0"), None);
+        assert_eq!(timeout_duration("0ms"), None);
+        assert_eq!(timeout_duration("0h"), None);
+        assert_eq!(timeout_duration("1ms"), Some(Duration::from_millis(1)));
+        assert_eq!(timeout_duration("5s"), Some(Duration::from_secs(5)));
+    }
+
+    // An imported function and two functions that are both called "dup".
+    #[rustfmt::skip]
+    const LCOV_MODULE: &[u8] = &[
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+        // (type (func))
+        0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+        // (import "env" "f" (func (type 0)))
+        0x02, 0x09, 0x01, 0x03, 0x65, 0x6e, 0x76, 0x01, 0x66, 0x00, 0x00,
+        // (func (type 0)) (func (type 0))
+        0x03, 0x03, 0x02, 0x00, 0x00,
+        // return, return
+        0x0a, 0x09, 0x02, 0x03, 0x00, 0x0f, 0x0b, 0x03, 0x00, 0x0f, 0x0b,
+        // name section naming func 1 and func 2 "dup"
+        0x00, 0x12, 0x04, 0x6e, 0x61, 0x6d, 0x65, 0x01, 0x0b, 0x02, 0x01, 0x03, 0x64, 0x75, 0x70,
+        0x02, 0x03, 0x64, 0x75, 0x70,
+    ];
+
+    #[test]
+    fn write_lcov_names() {
+        let module = Module::parse_bytes(LCOV_MODULE).unwrap();
+        let mut coverage = Coverage {
+            calls: BTreeMap::new(),
+        };
+        coverage.call(1);
+        coverage.call(1);
+        let mut out = vec![];
+        coverage.write_lcov(&module, "test.wasm", &mut out).unwrap();
+        let expected = "SF:test.wasm\n\
+                        FN:2,dup[1]\n\
+                        FN:3,dup[2]\n\
+                        FNDA:2,dup[1]\n\
+                        FNDA:0,dup[2]\n\
+                        FNF:2\n\
+                        FNH:1\n\
+                        end_of_record\n";
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+}
@@ -0,0 +1,41 @@
+use motor::binary::{ExternalKind, Module};
+use std::collections::BTreeMap;
+
+// Names functions for diagnostics after the name section, falling back to
+// export names and then to their index.
+pub struct FuncNames {
+    names: BTreeMap<u32, String>,
+}
+
+impl FuncNames {
+    pub fn new(module: &Module) -> FuncNames {
+        let mut names = BTreeMap::new();
+        for entry in module.exports() {
+            if entry.kind() == ExternalKind::Function {
+                names.insert(entry.index(), entry.field_name().to_string());
+            }
+        }
+        match module.function_names() {
+            Ok(function_names) => names.extend(function_names),
+            Err(e) => warn!("malformed name section: {:?}", e),
+        }
+        FuncNames { names: names }
+    }
+
+    pub fn name(&self, func_idx: u32) -> String {
+        match self.names.get(&func_idx) {
+            Some(name) => name.clone(),
+            None => format!("func[{}]", func_idx),
+        }
+    }
+
+    // Returns the name of a function followed by its signature, for
+    // example "fib(i32) -> i32".
+    pub fn describe(&self, module: &Module, func_idx: u32) -> String {
+        let name = self.name(func_idx);
+        match module.func_type(func_idx) {
+            Some(ty) => format!("{}{}", name, ty),
+            None => name,
+        }
+    }
+}
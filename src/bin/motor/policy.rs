@@ -0,0 +1,102 @@
+use clap::ArgMatches;
+use motor::binary::Module;
+
+// Restricts the imports a module may request. Patterns match against
+// "module.field" and may contain '*' wildcards. A denied import is refused
+// even if it is also allowed.
+pub struct ImportPolicy {
+    allow: Vec<String>,
+    deny: Vec<String>,
+}
+
+impl ImportPolicy {
+    pub fn from_matches(matches: &ArgMatches) -> ImportPolicy {
+        let patterns = |name| match matches.values_of(name) {
+            Some(values) => values.map(|value| value.to_string()).collect(),
+            None => vec![],
+        };
+        ImportPolicy {
+            allow: patterns("allow-import"),
+            deny: patterns("deny-import"),
+        }
+    }
+
+    pub fn permits(&self, module_name: &str, field_name: &str) -> bool {
+        let name = format!("{}.{}", module_name, field_name);
+        let allowed = self.allow.is_empty() || self.allow.iter().any(|p| glob_match(p, &name));
+        let denied = self.deny.iter().any(|p| glob_match(p, &name));
+        allowed && !denied
+    }
+
+    // Returns the imports of a module that the policy refuses.
+    pub fn refused_imports(&self, module: &Module) -> Vec<String> {
+        module
+            .imports()
+            .iter()
+            .filter(|entry| !self.permits(entry.module_name(), entry.field_name()))
+            .map(|entry| format!("{}.{}", entry.module_name(), entry.field_name()))
+            .collect()
+    }
+}
+
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.as_bytes();
+    let text = text.as_bytes();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last '*' in the pattern and the text position it
+    // was matched against, for backtracking.
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            star = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{glob_match, ImportPolicy};
+
+    #[test]
+    fn glob_literal() {
+        assert!(glob_match("env.log", "env.log"));
+        assert!(!glob_match("env.log", "env.logs"));
+        assert!(!glob_match("env.log", "env.lo"));
+        assert!(glob_match("", ""));
+    }
+
+    #[test]
+    fn glob_wildcards() {
+        assert!(glob_match("env.*", "env.log"));
+        assert!(glob_match("env.*", "env."));
+        assert!(!glob_match("env.*", "wasi.log"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*.fd_*", "wasi_unstable.fd_write"));
+        assert!(!glob_match("*.fd_*", "wasi_unstable.path_open"));
+        assert!(glob_match("*a*b", "xaxxab"));
+        assert!(!glob_match("*a*b", "xaxxa"));
+        assert!(glob_match("a**", "a"));
+    }
+
+    #[test]
+    fn deny_overrides_allow() {
+        let policy = ImportPolicy {
+            allow: vec!["env.*".to_string()],
+            deny: vec!["env.exit".to_string()],
+        };
+        assert!(policy.permits("env", "log"));
+        assert!(!policy.permits("env", "exit"));
+        assert!(!policy.permits("wasi", "log"));
+    }
+}
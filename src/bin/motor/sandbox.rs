@@ -0,0 +1,149 @@
+// Confines the process with a seccomp-bpf filter once code has been
+// generated. The filter allows only the system calls the runtime needs from
+// then on: writing output, memory management for the allocator, and thread
+// and process exit. Any other system call kills the process.
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+pub use self::linux::enter;
+
+#[cfg(not(all(target_os = "linux", target_arch = "x86_64")))]
+pub fn enter() -> ::std::io::Result<()> {
+    Err(::std::io::Error::new(
+        ::std::io::ErrorKind::Other,
+        "sandboxing is only supported on x86-64 Linux",
+    ))
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+mod linux {
+    use std::io;
+
+    const PR_SET_NO_NEW_PRIVS: i32 = 38;
+    const SYS_SECCOMP: i64 = 317;
+    const SECCOMP_SET_MODE_FILTER: u32 = 1;
+    const SECCOMP_FILTER_FLAG_TSYNC: u32 = 1;
+    const SECCOMP_RET_KILL_PROCESS: u32 = 0x8000_0000;
+    const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+    const AUDIT_ARCH_X86_64: u32 = 0xc000_003e;
+    const PROT_EXEC: u32 = 0x4;
+
+    // Offsets into struct seccomp_data. Arguments are 64-bit, and the low
+    // half comes first on x86-64.
+    const DATA_NR: u32 = 0;
+    const DATA_ARCH: u32 = 4;
+    const DATA_ARG2: u32 = 16 + 2 * 8;
+
+    const BPF_LD_W_ABS: u16 = 0x20;
+    const BPF_JMP_JEQ_K: u16 = 0x15;
+    const BPF_JMP_JSET_K: u16 = 0x45;
+    const BPF_RET_K: u16 = 0x06;
+
+    const SYS_MMAP: u32 = 9;
+    const SYS_MPROTECT: u32 = 10;
+
+    // System calls that are allowed with any arguments.
+    const ALLOWED_SYSCALLS: &[u32] = &[
+        0,   // read
+        1,   // write
+        3,   // close
+        11,  // munmap
+        12,  // brk
+        14,  // rt_sigprocmask
+        15,  // rt_sigreturn
+        20,  // writev
+        24,  // sched_yield
+        25,  // mremap
+        28,  // madvise
+        60,  // exit
+        72,  // fcntl
+        131, // sigaltstack
+        202, // futex
+        219, // restart_syscall
+        228, // clock_gettime
+        231, // exit_group
+    ];
+
+    #[repr(C)]
+    struct SockFilter {
+        code: u16,
+        jt: u8,
+        jf: u8,
+        k: u32,
+    }
+
+    #[repr(C)]
+    struct SockFprog {
+        len: u16,
+        filter: *const SockFilter,
+    }
+
+    extern "C" {
+        fn prctl(option: i32, ...) -> i32;
+        fn syscall(number: i64, ...) -> i64;
+    }
+
+    fn stmt(code: u16, k: u32) -> SockFilter {
+        SockFilter {
+            code: code,
+            jt: 0,
+            jf: 0,
+            k: k,
+        }
+    }
+
+    fn jump(code: u16, k: u32, jt: u8, jf: u8) -> SockFilter {
+        SockFilter {
+            code: code,
+            jt: jt,
+            jf: jf,
+            k: k,
+        }
+    }
+
+    fn filter() -> Vec<SockFilter> {
+        let mut filter = vec![
+            stmt(BPF_LD_W_ABS, DATA_ARCH),
+            jump(BPF_JMP_JEQ_K, AUDIT_ARCH_X86_64, 1, 0),
+            stmt(BPF_RET_K, SECCOMP_RET_KILL_PROCESS),
+            stmt(BPF_LD_W_ABS, DATA_NR),
+        ];
+        for nr in ALLOWED_SYSCALLS {
+            filter.push(jump(BPF_JMP_JEQ_K, *nr, 0, 1));
+            filter.push(stmt(BPF_RET_K, SECCOMP_RET_ALLOW));
+        }
+        // The allocator may map and protect memory, but never executable
+        // memory.
+        filter.push(jump(BPF_JMP_JEQ_K, SYS_MMAP, 1, 0));
+        filter.push(jump(BPF_JMP_JEQ_K, SYS_MPROTECT, 0, 3));
+        filter.push(stmt(BPF_LD_W_ABS, DATA_ARG2));
+        filter.push(jump(BPF_JMP_JSET_K, PROT_EXEC, 1, 0));
+        filter.push(stmt(BPF_RET_K, SECCOMP_RET_ALLOW));
+        filter.push(stmt(BPF_RET_K, SECCOMP_RET_KILL_PROCESS));
+        filter
+    }
+
+    // Installs the filter on every thread of the process.
+    pub fn enter() -> io::Result<()> {
+        let filter = filter();
+        let prog = SockFprog {
+            len: filter.len() as u16,
+            filter: filter.as_ptr(),
+        };
+        unsafe {
+            if prctl(PR_SET_NO_NEW_PRIVS, 1u64, 0u64, 0u64, 0u64) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let prog_ptr = &prog as *const SockFprog;
+            if syscall(
+                SYS_SECCOMP,
+                SECCOMP_SET_MODE_FILTER,
+                SECCOMP_FILTER_FLAG_TSYNC,
+                prog_ptr,
+            ) != 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
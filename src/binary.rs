@@ -1,17 +1,31 @@
 // WebAssembly Binary Encoding Reference: https://github.com/WebAssembly/design/blob/master/BinaryEncoding.md
 
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use leb128;
-use std::fs::File;
-use std::io::{Error, Read};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Error, Read, Write};
+use std::ops::Range;
 use std::string;
+use std::sync::Arc;
+
+// Type constructor of function types, 0x60 encoded as a varint7.
+const FORM_FUNC: i8 = -0x20;
+
+// Name Section: https://github.com/WebAssembly/design/blob/master/BinaryEncoding.md#name-section
+const NAME_SUBSECTION_FUNCTION: u8 = 1;
 
 #[derive(Debug)]
 pub enum ParseError {
     BadMagic(u32),
     UnsupportedVersion(u32),
     InvalidValueType(i8),
+    InvalidElementType(i8),
     InvalidExternalKind(u8),
+    InvalidInitExpr(u8),
+    UnterminatedFunctionBody,
+    // A section whose contents end before its payload does, by section id.
+    SectionSizeMismatch(u32),
     IoError(Error),
     Utf8Error(string::FromUtf8Error),
     DecodeError(leb128::read::Error),
@@ -22,27 +36,93 @@ pub struct Module {
     magic_number: u32,
     version: u32,
     sections: Vec<Section>,
+    // Index of the first structurally equal type for every entry in the
+    // type section, so that equal types share an id.
+    type_ids: Vec<u32>,
 }
 
 #[derive(Debug)]
 enum Section {
-    Custom,
+    Custom { name: String, payload: Vec<u8> },
     Type { entries: Vec<FuncType> },
+    Import { entries: Vec<ImportEntry> },
     Function { types: Vec<u32> },
+    Table { entries: Vec<TableType> },
     Memory { entries: Vec<MemoryType> },
+    Global { entries: Vec<GlobalEntry> },
     Export { entries: Vec<ExportEntry> },
     Start { index: u32 },
     Code { bodies: Vec<FunctionBody> },
-    Unknown { id: u32 },
+    Unknown { id: u32, payload: Vec<u8> },
+}
+
+#[derive(Debug)]
+pub struct ImportEntry {
+    module_name: String,
+    field_name: String,
+    ty: ImportType,
+}
+
+#[derive(Debug)]
+pub enum ImportType {
+    Function(u32),
+    Table(TableType),
+    Memory(MemoryType),
+    Global(GlobalType),
+}
+
+#[derive(Debug)]
+pub struct TableType {
+    element_type: ElementType,
+    limits: ResizableLimits,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ElementType {
+    AnyFunc,
+    ExternRef,
 }
 
 #[derive(Debug)]
-struct MemoryType {
+pub struct MemoryType {
     limits: ResizableLimits,
 }
 
 #[derive(Debug)]
-enum ExternalKind {
+pub struct GlobalType {
+    content_type: ValueType,
+    mutable: bool,
+}
+
+#[derive(Debug)]
+struct GlobalEntry {
+    ty: GlobalType,
+    init: InitExpr,
+}
+
+#[derive(Debug)]
+enum InitExpr {
+    I32Const(i32),
+    I64Const(i64),
+    F32Const(u32),
+    F64Const(u64),
+    V128Const([u8; 16]),
+    GetGlobal(u32),
+    RefNull(ValueType),
+    RefFunc(u32),
+}
+
+// Type of an imported or exported entity.
+#[derive(Debug, Clone, Copy)]
+pub enum ExternType<'a> {
+    Func(&'a FuncType),
+    Table(&'a TableType),
+    Memory(&'a MemoryType),
+    Global(&'a GlobalType),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExternalKind {
     Function,
     Table,
     Memory,
@@ -50,22 +130,25 @@ enum ExternalKind {
 }
 
 #[derive(Debug)]
-struct ExportEntry {
+pub struct ExportEntry {
     field_name: String,
     kind: ExternalKind,
     index: u32,
 }
 
 #[derive(Debug)]
-struct ResizableLimits {
+pub struct ResizableLimits {
     initial: u32,
     maximum: Option<u32>,
+    shared: bool,
 }
 
-#[derive(Debug)]
 pub struct FunctionBody {
     pub locals: Vec<LocalEntry>,
-    pub code: Vec<u8>,
+    // Code of the body without the final end opcode, as a range of the
+    // module it was parsed from.
+    buf: Arc<Vec<u8>>,
+    code: Range<usize>,
 }
 
 #[derive(Debug)]
@@ -74,23 +157,48 @@ pub struct LocalEntry {
     ty: ValueType,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ValueType {
     I32,
     I64,
     F32,
     F64,
+    V128,
+    FuncRef,
+    ExternRef,
 }
 
-#[derive(Debug)]
-struct FuncType {
+// Function types match when they are equal, since there is no subtyping
+// between value types.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FuncType {
     form: i8,
     param_types: Vec<ValueType>,
-    return_type: Option<ValueType>,
+    return_types: Vec<ValueType>,
+}
+
+// Tool Conventions: https://github.com/WebAssembly/tool-conventions/blob/master/ProducersSection.md
+#[derive(Debug)]
+pub struct ProducersField {
+    name: String,
+    values: Vec<ProducerValue>,
+}
+
+#[derive(Debug)]
+pub struct ProducerValue {
+    name: String,
+    version: String,
 }
 
 impl Module {
     pub fn find_start_func(&self) -> Option<&FunctionBody> {
+        match self.start_func_index() {
+            Some(idx) => self.find_func(idx as usize),
+            None => None,
+        }
+    }
+
+    pub fn start_func_index(&self) -> Option<u32> {
         let mut start_idx: Option<u32> = None;
         for section in &self.sections {
             match section {
@@ -98,285 +206,1947 @@ impl Module {
                 _ => (),
             }
         }
-        match start_idx {
-            Some(idx) => self.find_func(idx as usize),
-            None => None,
-        }
+        start_idx
     }
 
-    fn find_func(&self, idx: usize) -> Option<&FunctionBody> {
-        for section in &self.sections {
-            match section {
-                Section::Code { bodies } => return Some(&bodies[idx]),
+    // Returns the function to run a module with: the start function if
+    // there is one, and otherwise an exported `_start` or `main` function.
+    pub fn entry_func_index(&self) -> Option<u32> {
+        self.start_func_index()
+            .or_else(|| self.find_export_func_index("_start"))
+            .or_else(|| self.find_export_func_index("main"))
+    }
+
+    pub fn find_export_func_index(&self, name: &str) -> Option<u32> {
+        for entry in self.exports() {
+            match entry.kind {
+                ExternalKind::Function if entry.field_name == name => return Some(entry.index),
                 _ => (),
             }
         }
         None
     }
 
-    pub fn parse(f: &mut File) -> Result<Module, ParseError> {
-        let magic_number = f.read_u32::<LittleEndian>().unwrap();
-        if magic_number != 0x6d736100 {
-            return Err(ParseError::BadMagic(magic_number));
+    pub fn get_export_type<'a>(&'a self, name: &str) -> Option<ExternType<'a>> {
+        for entry in self.exports() {
+            if entry.field_name == name {
+                return self.extern_type(entry.kind, entry.index);
+            }
         }
-        let version = f.read_u32::<LittleEndian>().unwrap();
-        if version != 0x01 {
-            return Err(ParseError::UnsupportedVersion(version));
+        None
+    }
+
+    pub fn func_export_name(&self, idx: u32) -> Option<&str> {
+        for entry in self.exports() {
+            match entry.kind {
+                ExternalKind::Function if entry.index == idx => return Some(&entry.field_name),
+                _ => (),
+            }
         }
-        let mut sections = vec![];
-        loop {
-            let section = try!(Section::parse(f));
-            if section.is_none() {
-                break;
+        None
+    }
+
+    // Function indices start with imported functions, which have no body.
+    pub fn find_func(&self, idx: usize) -> Option<&FunctionBody> {
+        let imported = self.imported_count(ExternalKind::Function);
+        if idx < imported {
+            return None;
+        }
+        for section in &self.sections {
+            match section {
+                Section::Code { bodies } => return bodies.get(idx - imported),
+                _ => (),
             }
-            sections.push(section.unwrap());
         }
-        return Ok(Module {
-            magic_number: magic_number,
-            version: version,
-            sections: sections,
-        });
+        None
     }
-}
 
-impl Section {
-    fn parse(f: &mut File) -> Result<Option<Section>, ParseError> {
-        let id = match Section::parse_varuint32(f) {
-            Err(_) => return Ok(None),
-            Ok(val) => val,
-        };
-        let payload_len = try!(Section::parse_varuint32(f)) as usize;
-        match id {
-            0 => Section::parse_custom_section(f, payload_len),
-            1 => Section::parse_type_section(f),
-            3 => Section::parse_function_section(f),
-            7 => Section::parse_export_section(f),
-            8 => Section::parse_start_section(f),
-            5 => Section::parse_memory_section(f),
-            10 => Section::parse_code_section(f),
-            _ => Section::parse_unknown_section(f, id, payload_len),
+    pub fn func_type(&self, idx: u32) -> Option<&FuncType> {
+        match self.func_type_index(idx) {
+            Some(type_idx) => self.types().get(type_idx as usize),
+            None => None,
         }
     }
 
-    fn parse_custom_section(
-        f: &mut File,
-        payload_len: usize,
-    ) -> Result<Option<Section>, ParseError> {
-        let name_len = try!(Section::parse_varuint32(f));
-        let mut name = vec![0u8; name_len as usize];
-        if let Err(e) = f.read_exact(&mut name) {
-            return Err(ParseError::IoError(e));
+    // Returns an id for the type of a function. Two functions have the
+    // same type id exactly when their types are equal.
+    pub fn func_type_id(&self, idx: u32) -> Option<u32> {
+        match self.func_type_index(idx) {
+            Some(type_idx) => self.type_id(type_idx),
+            None => None,
         }
-        let mut payload = vec![0u8; payload_len as usize];
-        if let Err(e) = f.read_exact(&mut payload) {
-            return Err(ParseError::IoError(e));
+    }
+
+    // Returns the id of an entry in the type section, as used by
+    // call_indirect.
+    pub fn type_id(&self, type_idx: u32) -> Option<u32> {
+        self.type_ids.get(type_idx as usize).cloned()
+    }
+
+    pub fn table_type(&self, idx: u32) -> Option<&TableType> {
+        let imported: Vec<&TableType> = self
+            .imports()
+            .iter()
+            .filter_map(|entry| match entry.ty {
+                ImportType::Table(ref ty) => Some(ty),
+                _ => None,
+            })
+            .collect();
+        match imported.get(idx as usize) {
+            Some(ty) => Some(*ty),
+            None => self.tables().get(idx as usize - imported.len()),
         }
-        Ok(Some(Section::Custom))
     }
 
-    fn parse_type_section(f: &mut File) -> Result<Option<Section>, ParseError> {
-        let mut entries = vec![];
-        let count = try!(Section::parse_varuint32(f));
-        for _ in 0..count {
-            let entry = try!(Section::parse_func_type(f));
-            entries.push(entry);
+    pub fn memory_type(&self, idx: u32) -> Option<&MemoryType> {
+        let imported: Vec<&MemoryType> = self
+            .imports()
+            .iter()
+            .filter_map(|entry| match entry.ty {
+                ImportType::Memory(ref ty) => Some(ty),
+                _ => None,
+            })
+            .collect();
+        match imported.get(idx as usize) {
+            Some(ty) => Some(*ty),
+            None => self.memories().get(idx as usize - imported.len()),
         }
-        Ok(Some(Section::Type { entries: entries }))
     }
 
-    fn parse_func_type(f: &mut File) -> Result<FuncType, ParseError> {
-        let form = try!(Section::parse_varint7(f));
-        let mut param_types = vec![];
-        let param_count = try!(Section::parse_varuint32(f));
-        for _ in 0..param_count {
-            let ty = try!(Section::parse_value_type(f));
-            param_types.push(ty);
+    pub fn global_type(&self, idx: u32) -> Option<&GlobalType> {
+        let imported: Vec<&GlobalType> = self
+            .imports()
+            .iter()
+            .filter_map(|entry| match entry.ty {
+                ImportType::Global(ref ty) => Some(ty),
+                _ => None,
+            })
+            .collect();
+        match imported.get(idx as usize) {
+            Some(ty) => Some(*ty),
+            None => self
+                .globals()
+                .get(idx as usize - imported.len())
+                .map(|entry| &entry.ty),
         }
-        let return_count = try!(Section::parse_varuint1(f));
-        let return_type = if return_count > 0 {
-            let ty = try!(Section::parse_value_type(f));
-            Some(ty)
-        } else {
-            None
-        };
-        Ok(FuncType {
-            form: form,
-            param_types: param_types,
-            return_type: return_type,
-        })
     }
 
-    fn parse_function_section(f: &mut File) -> Result<Option<Section>, ParseError> {
-        let mut types = vec![];
-        let count = try!(Section::parse_varuint32(f));
-        for _ in 0..count {
-            let ty = try!(Section::parse_varuint32(f));
-            types.push(ty);
+    pub fn imports(&self) -> &[ImportEntry] {
+        for section in &self.sections {
+            match section {
+                Section::Import { entries } => return entries,
+                _ => (),
+            }
         }
-        Ok(Some(Section::Function { types: types }))
+        &[]
     }
 
-    fn parse_export_section(f: &mut File) -> Result<Option<Section>, ParseError> {
-        let mut entries = vec![];
-        let count = try!(Section::parse_varuint32(f));
-        for _ in 0..count {
-            let entry = try!(Section::parse_export_entry(f));
-            entries.push(entry);
+    pub fn exports(&self) -> &[ExportEntry] {
+        for section in &self.sections {
+            match section {
+                Section::Export { entries } => return entries,
+                _ => (),
+            }
         }
-        Ok(Some(Section::Export { entries: entries }))
+        &[]
     }
 
-    fn parse_export_entry(f: &mut File) -> Result<ExportEntry, ParseError> {
-        let field_len = try!(Section::parse_varuint32(f));
-        let mut field_str = vec![0u8; field_len as usize];
-        if let Err(e) = f.read_exact(&mut field_str) {
-            return Err(ParseError::IoError(e));
+    // Pairs every import with its type. Function imports are resolved through
+    // the function index space, which starts with the imported functions.
+    pub fn import_types<'a>(&'a self) -> Vec<(&'a str, &'a str, Option<ExternType<'a>>)> {
+        let mut func_idx = 0;
+        let mut imports = vec![];
+        for entry in self.imports() {
+            let ty = match entry.ty {
+                ImportType::Function(_) => {
+                    let ty = self.func_type(func_idx).map(ExternType::Func);
+                    func_idx += 1;
+                    ty
+                }
+                ImportType::Table(ref ty) => Some(ExternType::Table(ty)),
+                ImportType::Memory(ref ty) => Some(ExternType::Memory(ty)),
+                ImportType::Global(ref ty) => Some(ExternType::Global(ty)),
+            };
+            imports.push((&entry.module_name[..], &entry.field_name[..], ty));
         }
-        let mut external_kind = [0; 1];
-        if let Err(e) = f.read_exact(&mut external_kind) {
-            return Err(ParseError::IoError(e));
+        imports
+    }
+
+    pub fn export_types<'a>(&'a self) -> Vec<(&'a str, Option<ExternType<'a>>)> {
+        self.exports()
+            .iter()
+            .map(|entry| {
+                let ty = self.extern_type(entry.kind, entry.index);
+                (&entry.field_name[..], ty)
+            })
+            .collect()
+    }
+
+    pub fn tables(&self) -> &[TableType] {
+        for section in &self.sections {
+            match section {
+                Section::Table { entries } => return entries,
+                _ => (),
+            }
         }
-        let kind = match external_kind[0] {
-            0 => ExternalKind::Function,
-            1 => ExternalKind::Table,
-            2 => ExternalKind::Memory,
-            3 => ExternalKind::Global,
-            _ => return Err(ParseError::InvalidExternalKind(external_kind[0])),
-        };
-        let index = try!(Section::parse_varuint32(f));
-        let field_name = match String::from_utf8(field_str) {
-            Err(e) => return Err(ParseError::Utf8Error(e)),
-            Ok(val) => val,
-        };
-        Ok(ExportEntry {
-            field_name: field_name,
-            kind: kind,
-            index: index,
-        })
+        &[]
     }
 
-    fn parse_memory_section(f: &mut File) -> Result<Option<Section>, ParseError> {
-        let mut entries = vec![];
-        let count = try!(Section::parse_varuint32(f));
+    pub fn memories(&self) -> &[MemoryType] {
+        for section in &self.sections {
+            match section {
+                Section::Memory { entries } => return entries,
+                _ => (),
+            }
+        }
+        &[]
+    }
+
+    pub fn producers(&self) -> Result<Vec<ProducersField>, ParseError> {
+        let mut fields = vec![];
+        let mut payload = match self.custom_section("producers") {
+            Some(payload) => payload,
+            None => return Ok(fields),
+        };
+        let count = try!(Section::parse_varuint32(&mut payload));
         for _ in 0..count {
-            let entry = try!(Section::parse_memory_type(f));
-            entries.push(entry);
+            let field = try!(Section::parse_producers_field(&mut payload));
+            fields.push(field);
         }
-        Ok(Some(Section::Memory { entries: entries }))
+        Ok(fields)
     }
 
-    fn parse_start_section(f: &mut File) -> Result<Option<Section>, ParseError> {
-        let index = try!(Section::parse_varuint32(f));
-        Ok(Some(Section::Start {
-            index: index as u32,
-        }))
+    // Returns the function names recorded in the name section.
+    pub fn function_names(&self) -> Result<Vec<(u32, String)>, ParseError> {
+        let mut names = vec![];
+        let mut payload = match self.custom_section("name") {
+            Some(payload) => payload,
+            None => return Ok(names),
+        };
+        while !payload.is_empty() {
+            let id = try!(Section::parse_byte(&mut payload));
+            let size = try!(Section::parse_varuint32(&mut payload));
+            let subsection = try!(Section::parse_exact(&mut payload, size as usize));
+            // Only function names are of interest; skip module and local
+            // names.
+            if id != NAME_SUBSECTION_FUNCTION {
+                continue;
+            }
+            let mut reader = &subsection[..];
+            let count = try!(Section::parse_varuint32(&mut reader));
+            for _ in 0..count {
+                let idx = try!(Section::parse_varuint32(&mut reader));
+                let name = try!(Section::parse_name(&mut reader));
+                names.push((idx, name));
+            }
+        }
+        Ok(names)
     }
 
-    fn parse_code_section(f: &mut File) -> Result<Option<Section>, ParseError> {
-        let mut bodies = vec![];
-        let count = try!(Section::parse_varuint32(f));
-        for _ in 0..count {
-            let body = try!(Section::parse_function_body(f));
-            bodies.push(body);
+    // Returns the names of post-MVP features that are recognizably used by
+    // the module's types. Features that only show up in code are not
+    // detected.
+    pub fn required_features(&self) -> Vec<&'static str> {
+        let mut features = vec![];
+        let mutable_global_import = self.imports().iter().any(|entry| match entry.ty {
+            ImportType::Global(ref ty) => ty.mutable,
+            _ => false,
+        });
+        let mutable_global_export = self.exports().iter().any(|entry| match entry.kind {
+            ExternalKind::Global => self.global_type(entry.index).map_or(false, |ty| ty.mutable),
+            _ => false,
+        });
+        if mutable_global_import || mutable_global_export {
+            features.push("mutable-global");
         }
-        Ok(Some(Section::Code { bodies: bodies }))
+        if self.types().iter().any(|ty| ty.return_types.len() > 1) {
+            features.push("multi-value");
+        }
+        let value_types = self.value_types();
+        let num_tables = self.imported_count(ExternalKind::Table) + self.tables().len();
+        let extern_ref_table = (0..num_tables).any(|idx| {
+            self.table_type(idx as u32)
+                .map_or(false, |ty| ty.element_type == ElementType::ExternRef)
+        });
+        let ref_value = value_types
+            .iter()
+            .any(|ty| *ty == ValueType::FuncRef || *ty == ValueType::ExternRef);
+        if num_tables > 1 || extern_ref_table || ref_value {
+            features.push("reference-types");
+        }
+        let shared_memory = (0..self.imported_count(ExternalKind::Memory) + self.memories().len())
+            .any(|idx| {
+                self.memory_type(idx as u32)
+                    .map_or(false, |ty| ty.limits.shared)
+            });
+        if shared_memory {
+            features.push("threads");
+        }
+        let data_count = self.sections.iter().any(|section| match section {
+            Section::Unknown { id: 12, .. } => true,
+            _ => false,
+        });
+        if data_count {
+            features.push("bulk-memory");
+        }
+        if value_types.contains(&ValueType::V128) {
+            features.push("simd");
+        }
+        features
     }
 
-    fn parse_function_body(f: &mut File) -> Result<FunctionBody, ParseError> {
-        let _body_size = try!(Section::parse_varuint32(f));
-        let mut locals = vec![];
-        let local_count = try!(Section::parse_varuint32(f));
-        for _ in 0..local_count {
-            let local = try!(Section::parse_local_entry(f));
-            locals.push(local);
+    // Returns every value type used by function types, globals and locals.
+    fn value_types(&self) -> Vec<ValueType> {
+        let mut value_types = vec![];
+        for ty in self.types() {
+            value_types.extend(&ty.param_types);
+            value_types.extend(&ty.return_types);
         }
-        let mut code = vec![];
-        loop {
-            let mut buf = [0; 1];
-            if let Err(e) = f.read_exact(&mut buf) {
-                return Err(ParseError::IoError(e));
+        for entry in self.imports() {
+            match entry.ty {
+                ImportType::Global(ref ty) => value_types.push(ty.content_type),
+                _ => (),
             }
-            if buf[0] == 0x0b {
-                break;
+        }
+        for entry in self.globals() {
+            value_types.push(entry.ty.content_type);
+        }
+        for section in &self.sections {
+            match section {
+                Section::Code { bodies } => {
+                    for body in bodies {
+                        value_types.extend(body.locals.iter().map(|local| local.ty));
+                    }
+                }
+                _ => (),
             }
-            code.push(buf[0]);
         }
-        Ok(FunctionBody {
-            locals: locals,
-            code: code,
-        })
+        value_types
     }
 
-    fn parse_local_entry(f: &mut File) -> Result<LocalEntry, ParseError> {
-        let count = try!(Section::parse_varuint32(f));
-        let ty = try!(Section::parse_value_type(f));
-        Ok(LocalEntry {
-            count: count,
-            ty: ty,
-        })
+    fn extern_type<'a>(&'a self, kind: ExternalKind, idx: u32) -> Option<ExternType<'a>> {
+        match kind {
+            ExternalKind::Function => self.func_type(idx).map(ExternType::Func),
+            ExternalKind::Table => self.table_type(idx).map(ExternType::Table),
+            ExternalKind::Memory => self.memory_type(idx).map(ExternType::Memory),
+            ExternalKind::Global => self.global_type(idx).map(ExternType::Global),
+        }
     }
 
-    fn parse_unknown_section(
-        f: &mut File,
-        id: u32,
-        payload_len: usize,
-    ) -> Result<Option<Section>, ParseError> {
-        let mut payload = vec![0u8; payload_len as usize];
-        if let Err(e) = f.read_exact(&mut payload) {
-            return Err(ParseError::IoError(e));
+    fn types(&self) -> &[FuncType] {
+        for section in &self.sections {
+            match section {
+                Section::Type { entries } => return entries,
+                _ => (),
+            }
         }
-        Ok(Some(Section::Unknown { id: id }))
+        &[]
     }
 
-    fn parse_memory_type(f: &mut File) -> Result<MemoryType, ParseError> {
-        let limits = try!(Section::parse_resizable_limits(f));
-        Ok(MemoryType { limits: limits })
+    fn func_type_index(&self, idx: u32) -> Option<u32> {
+        let imported: Vec<u32> = self
+            .imports()
+            .iter()
+            .filter_map(|entry| match entry.ty {
+                ImportType::Function(type_idx) => Some(type_idx),
+                _ => None,
+            })
+            .collect();
+        match imported.get(idx as usize) {
+            Some(type_idx) => Some(*type_idx),
+            None => self
+                .func_type_indices()
+                .get(idx as usize - imported.len())
+                .cloned(),
+        }
     }
 
-    fn parse_resizable_limits(f: &mut File) -> Result<ResizableLimits, ParseError> {
-        let flags = try!(Section::parse_varuint1(f));
-        let initial = try!(Section::parse_varuint32(f));
-        let maximum = if flags == 1 {
-            let maximum_raw = try!(Section::parse_varuint32(f));
-            Some(maximum_raw)
-        } else {
-            None
-        };
-        Ok(ResizableLimits {
-            initial: initial,
-            maximum: maximum,
-        })
+    fn intern_types(types: &[FuncType]) -> Vec<u32> {
+        let mut ids: HashMap<&FuncType, u32> = HashMap::new();
+        types
+            .iter()
+            .enumerate()
+            .map(|(idx, ty)| *ids.entry(ty).or_insert(idx as u32))
+            .collect()
     }
 
-    fn parse_value_type(f: &mut File) -> Result<ValueType, ParseError> {
-        let ty = try!(Section::parse_varint7(f));
-        match ty {
-            -0x01 => Ok(ValueType::I32),
-            -0x02 => Ok(ValueType::I64),
-            -0x03 => Ok(ValueType::F32),
-            -0x04 => Ok(ValueType::F64),
-            _ => Err(ParseError::InvalidValueType(ty)),
+    fn func_type_indices(&self) -> &[u32] {
+        for section in &self.sections {
+            match section {
+                Section::Function { types } => return types,
+                _ => (),
+            }
+        }
+        &[]
+    }
+
+    fn globals(&self) -> &[GlobalEntry] {
+        for section in &self.sections {
+            match section {
+                Section::Global { entries } => return entries,
+                _ => (),
+            }
         }
+        &[]
     }
 
-    fn parse_varuint32(f: &mut File) -> Result<u32, ParseError> {
-        match leb128::read::signed(f) {
-            Err(e) => return Err(ParseError::DecodeError(e)),
-            Ok(val) => return Ok(val as u32),
+    // Returns the payload of the first custom section called `name`.
+    pub fn custom_section(&self, name: &str) -> Option<&[u8]> {
+        for section in &self.sections {
+            match section {
+                Section::Custom {
+                    name: section_name,
+                    payload,
+                } if section_name == name => return Some(payload),
+                _ => (),
+            }
         }
+        None
+    }
+
+    fn imported_count(&self, kind: ExternalKind) -> usize {
+        self.imports()
+            .iter()
+            .filter(|entry| entry.kind() == kind)
+            .count()
     }
 
-    fn parse_varint7(f: &mut File) -> Result<i8, ParseError> {
-        match leb128::read::signed(f) {
-            Err(e) => return Err(ParseError::DecodeError(e)),
-            Ok(val) => return Ok(val as i8),
+    // Reads the whole module into memory first so that decoding doesn't
+    // issue a read for every byte.
+    pub fn parse<R: Read>(f: &mut R) -> Result<Module, ParseError> {
+        let mut bytes = vec![];
+        if let Err(e) = f.read_to_end(&mut bytes) {
+            return Err(ParseError::IoError(e));
         }
+        Module::parse_buffer(Arc::new(bytes))
+    }
+
+    pub fn parse_bytes(bytes: &[u8]) -> Result<Module, ParseError> {
+        Module::parse_buffer(Arc::new(bytes.to_vec()))
     }
 
-    fn parse_varuint1(f: &mut File) -> Result<u8, ParseError> {
-        match leb128::read::signed(f) {
-            Err(e) => return Err(ParseError::DecodeError(e)),
-            Ok(val) => return Ok(val as u8),
+    // Function bodies keep a reference to the buffer instead of copying
+    // their code out of it.
+    fn parse_buffer(buf: Arc<Vec<u8>>) -> Result<Module, ParseError> {
+        let f = &mut &buf[..];
+        let magic_number = match f.read_u32::<LittleEndian>() {
+            Ok(magic_number) => magic_number,
+            Err(e) => return Err(ParseError::IoError(e)),
+        };
+        if magic_number != 0x6d736100 {
+            return Err(ParseError::BadMagic(magic_number));
         }
+        let version = match f.read_u32::<LittleEndian>() {
+            Ok(version) => version,
+            Err(e) => return Err(ParseError::IoError(e)),
+        };
+        if version != 0x01 {
+            return Err(ParseError::UnsupportedVersion(version));
+        }
+        let mut sections = vec![];
+        loop {
+            let section = try!(Section::parse(f, &buf));
+            if section.is_none() {
+                break;
+            }
+            sections.push(section.unwrap());
+        }
+        debug!("parsed module with {} sections", sections.len());
+        let mut module = Module {
+            magic_number: magic_number,
+            version: version,
+            sections: sections,
+            type_ids: vec![],
+        };
+        module.type_ids = Module::intern_types(module.types());
+        return Ok(module);
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        try!(w.write_u32::<LittleEndian>(self.magic_number));
+        try!(w.write_u32::<LittleEndian>(self.version));
+        for section in &self.sections {
+            try!(section.write(w));
+        }
+        Ok(())
+    }
+
+    pub fn retain_custom_sections<F: Fn(&str) -> bool>(&mut self, f: F) {
+        self.sections.retain(|section| match section {
+            Section::Custom { name, .. } => f(name),
+            _ => true,
+        });
+    }
+
+    // Returns the name and payload of every custom section, in module order.
+    pub fn custom_sections<'a>(&'a self) -> Vec<(&'a str, &'a [u8])> {
+        let mut sections = vec![];
+        for section in &self.sections {
+            match section {
+                Section::Custom { name, payload } => sections.push((&name[..], &payload[..])),
+                _ => (),
+            }
+        }
+        sections
+    }
+
+    // Appends a custom section after all other sections. Custom sections
+    // may repeat, so an existing section with the same name is kept.
+    pub fn add_custom_section(&mut self, name: &str, payload: Vec<u8>) {
+        self.sections.push(Section::Custom {
+            name: name.to_string(),
+            payload: payload,
+        });
+    }
+
+    // Replaces the payload of the first custom section called `name`, or
+    // adds the section if there is none.
+    pub fn set_custom_section(&mut self, name: &str, payload: Vec<u8>) {
+        for section in &mut self.sections {
+            match section {
+                Section::Custom {
+                    name: section_name,
+                    payload: section_payload,
+                } if section_name == name => {
+                    *section_payload = payload;
+                    return;
+                }
+                _ => (),
+            }
+        }
+        self.add_custom_section(name, payload);
+    }
+
+    // Removes every custom section called `name`, returning how many were
+    // removed.
+    pub fn remove_custom_section(&mut self, name: &str) -> usize {
+        let len = self.sections.len();
+        self.retain_custom_sections(|section_name| section_name != name);
+        len - self.sections.len()
+    }
+
+    // Renames the module of every import from `from`, returning the number of
+    // imports renamed.
+    pub fn rename_import_module(&mut self, from: &str, to: &str) -> usize {
+        let mut count = 0;
+        for entry in self.imports_mut() {
+            if entry.module_name == from {
+                entry.module_name = to.to_string();
+                count += 1;
+            }
+        }
+        count
+    }
+
+    pub fn rename_import(
+        &mut self,
+        module_name: &str,
+        field_name: &str,
+        new_module_name: &str,
+        new_field_name: &str,
+    ) -> bool {
+        for entry in self.imports_mut() {
+            if entry.module_name == module_name && entry.field_name == field_name {
+                entry.module_name = new_module_name.to_string();
+                entry.field_name = new_field_name.to_string();
+                return true;
+            }
+        }
+        false
+    }
+
+    // Exports the entity at `index`. Fails if the name is already exported or
+    // there is no such entity.
+    pub fn add_export(&mut self, name: &str, kind: ExternalKind, index: u32) -> bool {
+        if self.exports().iter().any(|entry| entry.field_name == name) {
+            return false;
+        }
+        if self.extern_type(kind, index).is_none() {
+            return false;
+        }
+        self.insert_exports().push(ExportEntry {
+            field_name: name.to_string(),
+            kind: kind,
+            index: index,
+        });
+        true
+    }
+
+    pub fn remove_export(&mut self, name: &str) -> bool {
+        match self.exports_mut() {
+            Some(entries) => {
+                let len = entries.len();
+                entries.retain(|entry| entry.field_name != name);
+                entries.len() != len
+            }
+            None => false,
+        }
+    }
+
+    pub fn rename_export(&mut self, name: &str, new_name: &str) -> bool {
+        if self
+            .exports()
+            .iter()
+            .any(|entry| entry.field_name == new_name)
+        {
+            return false;
+        }
+        if let Some(entries) = self.exports_mut() {
+            for entry in entries {
+                if entry.field_name == name {
+                    entry.field_name = new_name.to_string();
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    // Exports the entity exported as `name` under `alias` as well.
+    pub fn alias_export(&mut self, name: &str, alias: &str) -> bool {
+        let (kind, index) = match self.exports().iter().find(|entry| entry.field_name == name) {
+            Some(entry) => (entry.kind, entry.index),
+            None => return false,
+        };
+        self.add_export(alias, kind, index)
+    }
+
+    fn imports_mut(&mut self) -> &mut [ImportEntry] {
+        for section in &mut self.sections {
+            match section {
+                Section::Import { entries } => return entries,
+                _ => (),
+            }
+        }
+        &mut []
+    }
+
+    fn exports_mut(&mut self) -> Option<&mut Vec<ExportEntry>> {
+        for section in &mut self.sections {
+            match section {
+                Section::Export { entries } => return Some(entries),
+                _ => (),
+            }
+        }
+        None
+    }
+
+    // Like exports_mut(), but adds an empty export section in its place in
+    // the section order if the module doesn't have one.
+    fn insert_exports(&mut self) -> &mut Vec<ExportEntry> {
+        let pos = match self.sections.iter().position(|section| match section {
+            Section::Export { .. } => true,
+            _ => false,
+        }) {
+            Some(pos) => pos,
+            None => {
+                let pos = self
+                    .sections
+                    .iter()
+                    .position(|section| section.id() > 7)
+                    .unwrap_or(self.sections.len());
+                self.sections
+                    .insert(pos, Section::Export { entries: vec![] });
+                pos
+            }
+        };
+        match self.sections[pos] {
+            Section::Export { ref mut entries } => entries,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl ImportEntry {
+    pub fn module_name(&self) -> &str {
+        &self.module_name
+    }
+
+    pub fn field_name(&self) -> &str {
+        &self.field_name
+    }
+
+    pub fn kind(&self) -> ExternalKind {
+        match self.ty {
+            ImportType::Function(_) => ExternalKind::Function,
+            ImportType::Table(_) => ExternalKind::Table,
+            ImportType::Memory(_) => ExternalKind::Memory,
+            ImportType::Global(_) => ExternalKind::Global,
+        }
+    }
+
+    pub fn ty(&self) -> &ImportType {
+        &self.ty
+    }
+}
+
+impl ExportEntry {
+    pub fn field_name(&self) -> &str {
+        &self.field_name
+    }
+
+    pub fn kind(&self) -> ExternalKind {
+        self.kind
+    }
+
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+}
+
+impl TableType {
+    pub fn element_type(&self) -> ElementType {
+        self.element_type
+    }
+
+    pub fn limits(&self) -> &ResizableLimits {
+        &self.limits
+    }
+}
+
+impl MemoryType {
+    pub fn limits(&self) -> &ResizableLimits {
+        &self.limits
+    }
+}
+
+impl GlobalType {
+    pub fn content_type(&self) -> ValueType {
+        self.content_type
+    }
+
+    pub fn is_mutable(&self) -> bool {
+        self.mutable
+    }
+}
+
+impl ResizableLimits {
+    pub fn initial(&self) -> u32 {
+        self.initial
+    }
+
+    pub fn maximum(&self) -> Option<u32> {
+        self.maximum
+    }
+
+    pub fn is_shared(&self) -> bool {
+        self.shared
+    }
+}
+
+impl FuncType {
+    pub fn new(param_types: Vec<ValueType>, return_types: Vec<ValueType>) -> FuncType {
+        FuncType {
+            form: FORM_FUNC,
+            param_types: param_types,
+            return_types: return_types,
+        }
+    }
+
+    pub fn param_types(&self) -> &[ValueType] {
+        &self.param_types
+    }
+
+    // More than one result requires the multi-value proposal.
+    pub fn return_types(&self) -> &[ValueType] {
+        &self.return_types
+    }
+}
+
+impl FunctionBody {
+    pub fn code(&self) -> &[u8] {
+        &self.buf[self.code.clone()]
+    }
+}
+
+impl fmt::Debug for FunctionBody {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FunctionBody")
+            .field("locals", &self.locals)
+            .field("code", &self.code())
+            .finish()
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ValueType::I32 => "i32",
+            ValueType::I64 => "i64",
+            ValueType::F32 => "f32",
+            ValueType::F64 => "f64",
+            ValueType::V128 => "v128",
+            ValueType::FuncRef => "funcref",
+            ValueType::ExternRef => "externref",
+        };
+        f.write_str(name)
+    }
+}
+
+// Formats a function type as its signature, for example "(i32, i32) -> i32".
+// Multiple results are parenthesized like parameters.
+impl fmt::Display for FuncType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let params: Vec<String> = self.param_types.iter().map(|ty| ty.to_string()).collect();
+        try!(write!(f, "({})", params.join(", ")));
+        match self.return_types.len() {
+            0 => Ok(()),
+            1 => write!(f, " -> {}", self.return_types[0]),
+            _ => {
+                let results: Vec<String> =
+                    self.return_types.iter().map(|ty| ty.to_string()).collect();
+                write!(f, " -> ({})", results.join(", "))
+            }
+        }
+    }
+}
+
+impl ProducersField {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn values(&self) -> &[ProducerValue] {
+        &self.values
+    }
+}
+
+impl ProducerValue {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+}
+
+impl Section {
+    // The module ends only where a section would start. Each section is
+    // parsed from exactly its payload.
+    fn parse(f: &mut &[u8], buf: &Arc<Vec<u8>>) -> Result<Option<Section>, ParseError> {
+        if f.is_empty() {
+            return Ok(None);
+        }
+        let id = try!(Section::parse_varuint32(f));
+        let payload_len = try!(Section::parse_varuint32(f)) as usize;
+        trace!("parsing section {} ({} bytes)", id, payload_len);
+        if payload_len > f.len() {
+            return Err(ParseError::IoError(Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "section extends past the end of the module",
+            )));
+        }
+        let offset = buf.len() - f.len();
+        let (mut payload, rest) = f.split_at(payload_len);
+        *f = rest;
+        let section = try!(match id {
+            0 => Section::parse_custom_section(&mut payload, payload_len),
+            1 => Section::parse_type_section(&mut payload),
+            2 => Section::parse_import_section(&mut payload),
+            3 => Section::parse_function_section(&mut payload),
+            4 => Section::parse_table_section(&mut payload),
+            7 => Section::parse_export_section(&mut payload),
+            8 => Section::parse_start_section(&mut payload),
+            5 => Section::parse_memory_section(&mut payload),
+            6 => Section::parse_global_section(&mut payload),
+            10 => Section::parse_code_section(&mut payload, buf, offset),
+            _ => Section::parse_unknown_section(&mut payload, id, payload_len),
+        });
+        if !payload.is_empty() {
+            return Err(ParseError::SectionSizeMismatch(id));
+        }
+        Ok(section)
+    }
+
+    fn parse_custom_section<R: Read>(
+        f: &mut R,
+        payload_len: usize,
+    ) -> Result<Option<Section>, ParseError> {
+        let payload = try!(Section::parse_exact(f, payload_len));
+        // The section name is part of the payload.
+        let mut reader = &payload[..];
+        let name = try!(Section::parse_name(&mut reader));
+        let payload = reader.to_vec();
+        Ok(Some(Section::Custom {
+            name: name,
+            payload: payload,
+        }))
+    }
+
+    fn parse_type_section<R: Read>(f: &mut R) -> Result<Option<Section>, ParseError> {
+        let mut entries = vec![];
+        let count = try!(Section::parse_varuint32(f));
+        for _ in 0..count {
+            let entry = try!(Section::parse_func_type(f));
+            entries.push(entry);
+        }
+        Ok(Some(Section::Type { entries: entries }))
+    }
+
+    fn parse_func_type<R: Read>(f: &mut R) -> Result<FuncType, ParseError> {
+        let form = try!(Section::parse_varint7(f));
+        let mut param_types = vec![];
+        let param_count = try!(Section::parse_varuint32(f));
+        for _ in 0..param_count {
+            let ty = try!(Section::parse_value_type(f));
+            param_types.push(ty);
+        }
+        let mut return_types = vec![];
+        let return_count = try!(Section::parse_varuint32(f));
+        for _ in 0..return_count {
+            let ty = try!(Section::parse_value_type(f));
+            return_types.push(ty);
+        }
+        Ok(FuncType {
+            form: form,
+            param_types: param_types,
+            return_types: return_types,
+        })
+    }
+
+    fn parse_import_section<R: Read>(f: &mut R) -> Result<Option<Section>, ParseError> {
+        let mut entries = vec![];
+        let count = try!(Section::parse_varuint32(f));
+        for _ in 0..count {
+            let entry = try!(Section::parse_import_entry(f));
+            entries.push(entry);
+        }
+        Ok(Some(Section::Import { entries: entries }))
+    }
+
+    fn parse_import_entry<R: Read>(f: &mut R) -> Result<ImportEntry, ParseError> {
+        let module_name = try!(Section::parse_name(f));
+        let field_name = try!(Section::parse_name(f));
+        let ty = match try!(Section::parse_external_kind(f)) {
+            ExternalKind::Function => ImportType::Function(try!(Section::parse_varuint32(f))),
+            ExternalKind::Table => ImportType::Table(try!(Section::parse_table_type(f))),
+            ExternalKind::Memory => ImportType::Memory(try!(Section::parse_memory_type(f))),
+            ExternalKind::Global => ImportType::Global(try!(Section::parse_global_type(f))),
+        };
+        Ok(ImportEntry {
+            module_name: module_name,
+            field_name: field_name,
+            ty: ty,
+        })
+    }
+
+    fn parse_function_section<R: Read>(f: &mut R) -> Result<Option<Section>, ParseError> {
+        let mut types = vec![];
+        let count = try!(Section::parse_varuint32(f));
+        for _ in 0..count {
+            let ty = try!(Section::parse_varuint32(f));
+            types.push(ty);
+        }
+        Ok(Some(Section::Function { types: types }))
+    }
+
+    fn parse_table_section<R: Read>(f: &mut R) -> Result<Option<Section>, ParseError> {
+        let mut entries = vec![];
+        let count = try!(Section::parse_varuint32(f));
+        for _ in 0..count {
+            let entry = try!(Section::parse_table_type(f));
+            entries.push(entry);
+        }
+        Ok(Some(Section::Table { entries: entries }))
+    }
+
+    fn parse_global_section<R: Read>(f: &mut R) -> Result<Option<Section>, ParseError> {
+        let mut entries = vec![];
+        let count = try!(Section::parse_varuint32(f));
+        for _ in 0..count {
+            let ty = try!(Section::parse_global_type(f));
+            let init = try!(Section::parse_init_expr(f));
+            entries.push(GlobalEntry { ty: ty, init: init });
+        }
+        Ok(Some(Section::Global { entries: entries }))
+    }
+
+    fn parse_export_section<R: Read>(f: &mut R) -> Result<Option<Section>, ParseError> {
+        let mut entries = vec![];
+        let count = try!(Section::parse_varuint32(f));
+        for _ in 0..count {
+            let entry = try!(Section::parse_export_entry(f));
+            entries.push(entry);
+        }
+        Ok(Some(Section::Export { entries: entries }))
+    }
+
+    fn parse_export_entry<R: Read>(f: &mut R) -> Result<ExportEntry, ParseError> {
+        let field_name = try!(Section::parse_name(f));
+        let kind = try!(Section::parse_external_kind(f));
+        let index = try!(Section::parse_varuint32(f));
+        Ok(ExportEntry {
+            field_name: field_name,
+            kind: kind,
+            index: index,
+        })
+    }
+
+    fn parse_external_kind<R: Read>(f: &mut R) -> Result<ExternalKind, ParseError> {
+        let mut external_kind = [0; 1];
+        if let Err(e) = f.read_exact(&mut external_kind) {
+            return Err(ParseError::IoError(e));
+        }
+        match external_kind[0] {
+            0 => Ok(ExternalKind::Function),
+            1 => Ok(ExternalKind::Table),
+            2 => Ok(ExternalKind::Memory),
+            3 => Ok(ExternalKind::Global),
+            _ => Err(ParseError::InvalidExternalKind(external_kind[0])),
+        }
+    }
+
+    fn parse_memory_section<R: Read>(f: &mut R) -> Result<Option<Section>, ParseError> {
+        let mut entries = vec![];
+        let count = try!(Section::parse_varuint32(f));
+        for _ in 0..count {
+            let entry = try!(Section::parse_memory_type(f));
+            entries.push(entry);
+        }
+        Ok(Some(Section::Memory { entries: entries }))
+    }
+
+    fn parse_start_section<R: Read>(f: &mut R) -> Result<Option<Section>, ParseError> {
+        let index = try!(Section::parse_varuint32(f));
+        Ok(Some(Section::Start {
+            index: index as u32,
+        }))
+    }
+
+    // The payload starts at offset in the module buffer.
+    fn parse_code_section(
+        f: &mut &[u8],
+        buf: &Arc<Vec<u8>>,
+        offset: usize,
+    ) -> Result<Option<Section>, ParseError> {
+        let payload_len = f.len();
+        let mut bodies = vec![];
+        let count = try!(Section::parse_varuint32(f));
+        for _ in 0..count {
+            let body_offset = offset + payload_len - f.len();
+            let body = try!(Section::parse_function_body(f, buf, body_offset));
+            bodies.push(body);
+        }
+        Ok(Some(Section::Code { bodies: bodies }))
+    }
+
+    // The reader starts at offset in the module buffer, which is where the
+    // code is located.
+    fn parse_function_body(
+        f: &mut &[u8],
+        buf: &Arc<Vec<u8>>,
+        offset: usize,
+    ) -> Result<FunctionBody, ParseError> {
+        let len = f.len();
+        let body_size = try!(Section::parse_varuint32(f)) as usize;
+        let body_offset = offset + len - f.len();
+        if body_size > f.len() {
+            return Err(ParseError::IoError(Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "function body extends past the end of the code section",
+            )));
+        }
+        let (mut reader, rest) = f.split_at(body_size);
+        *f = rest;
+        let mut locals = vec![];
+        let local_count = try!(Section::parse_varuint32(&mut reader));
+        for _ in 0..local_count {
+            let local = try!(Section::parse_local_entry(&mut reader));
+            locals.push(local);
+        }
+        // The final end opcode terminates the body and is not part of the code.
+        if reader.last() != Some(&0x0b) {
+            return Err(ParseError::UnterminatedFunctionBody);
+        }
+        let end = body_offset + body_size - 1;
+        let start = end + 1 - reader.len();
+        Ok(FunctionBody {
+            locals: locals,
+            buf: buf.clone(),
+            code: start..end,
+        })
+    }
+
+    fn parse_local_entry<R: Read>(f: &mut R) -> Result<LocalEntry, ParseError> {
+        let count = try!(Section::parse_varuint32(f));
+        let ty = try!(Section::parse_value_type(f));
+        Ok(LocalEntry {
+            count: count,
+            ty: ty,
+        })
+    }
+
+    fn parse_unknown_section<R: Read>(
+        f: &mut R,
+        id: u32,
+        payload_len: usize,
+    ) -> Result<Option<Section>, ParseError> {
+        let payload = try!(Section::parse_exact(f, payload_len));
+        debug!("skipped unknown section {}", id);
+        Ok(Some(Section::Unknown {
+            id: id,
+            payload: payload,
+        }))
+    }
+
+    fn parse_producers_field<R: Read>(f: &mut R) -> Result<ProducersField, ParseError> {
+        let name = try!(Section::parse_name(f));
+        let mut values = vec![];
+        let count = try!(Section::parse_varuint32(f));
+        for _ in 0..count {
+            let value_name = try!(Section::parse_name(f));
+            let version = try!(Section::parse_name(f));
+            values.push(ProducerValue {
+                name: value_name,
+                version: version,
+            });
+        }
+        Ok(ProducersField {
+            name: name,
+            values: values,
+        })
+    }
+
+    fn parse_table_type<R: Read>(f: &mut R) -> Result<TableType, ParseError> {
+        let ty = try!(Section::parse_varint7(f));
+        let element_type = match ty {
+            -0x10 => ElementType::AnyFunc,
+            -0x11 => ElementType::ExternRef,
+            _ => return Err(ParseError::InvalidElementType(ty)),
+        };
+        let limits = try!(Section::parse_resizable_limits(f));
+        Ok(TableType {
+            element_type: element_type,
+            limits: limits,
+        })
+    }
+
+    fn parse_memory_type<R: Read>(f: &mut R) -> Result<MemoryType, ParseError> {
+        let limits = try!(Section::parse_resizable_limits(f));
+        Ok(MemoryType { limits: limits })
+    }
+
+    fn parse_global_type<R: Read>(f: &mut R) -> Result<GlobalType, ParseError> {
+        let content_type = try!(Section::parse_value_type(f));
+        let mutability = try!(Section::parse_varuint1(f));
+        Ok(GlobalType {
+            content_type: content_type,
+            mutable: mutability == 1,
+        })
+    }
+
+    fn parse_init_expr<R: Read>(f: &mut R) -> Result<InitExpr, ParseError> {
+        let opcode = try!(Section::parse_byte(f));
+        let expr = match opcode {
+            0x41 => InitExpr::I32Const(try!(Section::parse_varint32(f))),
+            0x42 => InitExpr::I64Const(try!(Section::parse_varint64(f))),
+            0x43 => match f.read_u32::<LittleEndian>() {
+                Err(e) => return Err(ParseError::IoError(e)),
+                Ok(val) => InitExpr::F32Const(val),
+            },
+            0x44 => match f.read_u64::<LittleEndian>() {
+                Err(e) => return Err(ParseError::IoError(e)),
+                Ok(val) => InitExpr::F64Const(val),
+            },
+            0xfd => {
+                let opcode = try!(Section::parse_varuint32(f));
+                if opcode != 0x0c {
+                    return Err(ParseError::InvalidInitExpr(0xfd));
+                }
+                let mut val = [0; 16];
+                if let Err(e) = f.read_exact(&mut val) {
+                    return Err(ParseError::IoError(e));
+                }
+                InitExpr::V128Const(val)
+            }
+            0x23 => InitExpr::GetGlobal(try!(Section::parse_varuint32(f))),
+            0xd0 => InitExpr::RefNull(try!(Section::parse_value_type(f))),
+            0xd2 => InitExpr::RefFunc(try!(Section::parse_varuint32(f))),
+            _ => return Err(ParseError::InvalidInitExpr(opcode)),
+        };
+        let end = try!(Section::parse_byte(f));
+        if end != 0x0b {
+            return Err(ParseError::InvalidInitExpr(end));
+        }
+        Ok(expr)
+    }
+
+    fn parse_resizable_limits<R: Read>(f: &mut R) -> Result<ResizableLimits, ParseError> {
+        let flags = try!(Section::parse_varuint32(f));
+        let initial = try!(Section::parse_varuint32(f));
+        let maximum = if flags & 0x1 != 0 {
+            let maximum_raw = try!(Section::parse_varuint32(f));
+            Some(maximum_raw)
+        } else {
+            None
+        };
+        Ok(ResizableLimits {
+            initial: initial,
+            maximum: maximum,
+            shared: flags & 0x2 != 0,
+        })
+    }
+
+    fn parse_value_type<R: Read>(f: &mut R) -> Result<ValueType, ParseError> {
+        let ty = try!(Section::parse_varint7(f));
+        match ty {
+            -0x01 => Ok(ValueType::I32),
+            -0x02 => Ok(ValueType::I64),
+            -0x03 => Ok(ValueType::F32),
+            -0x04 => Ok(ValueType::F64),
+            -0x05 => Ok(ValueType::V128),
+            -0x10 => Ok(ValueType::FuncRef),
+            -0x11 => Ok(ValueType::ExternRef),
+            _ => Err(ParseError::InvalidValueType(ty)),
+        }
+    }
+
+    fn parse_name<R: Read>(f: &mut R) -> Result<String, ParseError> {
+        let len = try!(Section::parse_varuint32(f));
+        let bytes = try!(Section::parse_exact(f, len as usize));
+        match String::from_utf8(bytes) {
+            Err(e) => Err(ParseError::Utf8Error(e)),
+            Ok(val) => Ok(val),
+        }
+    }
+
+    // Reads len bytes without allocating them up front, so that a corrupt
+    // length fails with an error instead of a huge allocation.
+    fn parse_exact<R: Read>(f: &mut R, len: usize) -> Result<Vec<u8>, ParseError> {
+        let mut bytes = vec![];
+        if let Err(e) = f.by_ref().take(len as u64).read_to_end(&mut bytes) {
+            return Err(ParseError::IoError(e));
+        }
+        if bytes.len() != len {
+            return Err(ParseError::IoError(Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "unexpected end of module",
+            )));
+        }
+        Ok(bytes)
+    }
+
+    fn parse_byte<R: Read>(f: &mut R) -> Result<u8, ParseError> {
+        let mut buf = [0; 1];
+        if let Err(e) = f.read_exact(&mut buf) {
+            return Err(ParseError::IoError(e));
+        }
+        Ok(buf[0])
+    }
+
+    // Most LEB128 values in a module fit in a single byte, so decode those
+    // before falling back to the loop for longer encodings.
+    fn parse_unsigned<R: Read>(f: &mut R) -> Result<u64, ParseError> {
+        let byte = try!(Section::parse_byte(f));
+        if byte & 0x80 == 0 {
+            return Ok(byte as u64);
+        }
+        let mut val = (byte & 0x7f) as u64;
+        let mut shift = 7;
+        loop {
+            let byte = try!(Section::parse_byte(f));
+            if shift >= 64 {
+                return Err(ParseError::DecodeError(leb128::read::Error::Overflow));
+            }
+            val |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(val);
+            }
+            shift += 7;
+        }
+    }
+
+    fn parse_signed<R: Read>(f: &mut R) -> Result<i64, ParseError> {
+        let byte = try!(Section::parse_byte(f));
+        if byte & 0x80 == 0 {
+            // Sign-extend from bit 6.
+            return Ok((((byte << 1) as i8) >> 1) as i64);
+        }
+        let mut val = (byte & 0x7f) as i64;
+        let mut shift = 7;
+        loop {
+            let byte = try!(Section::parse_byte(f));
+            if shift >= 64 {
+                return Err(ParseError::DecodeError(leb128::read::Error::Overflow));
+            }
+            val |= ((byte & 0x7f) as i64) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    val |= -1 << shift;
+                }
+                return Ok(val);
+            }
+        }
+    }
+
+    fn parse_varuint32<R: Read>(f: &mut R) -> Result<u32, ParseError> {
+        Section::parse_unsigned(f).map(|val| val as u32)
+    }
+
+    fn parse_varint32<R: Read>(f: &mut R) -> Result<i32, ParseError> {
+        Section::parse_signed(f).map(|val| val as i32)
+    }
+
+    fn parse_varint64<R: Read>(f: &mut R) -> Result<i64, ParseError> {
+        Section::parse_signed(f)
+    }
+
+    fn parse_varint7<R: Read>(f: &mut R) -> Result<i8, ParseError> {
+        Section::parse_signed(f).map(|val| val as i8)
+    }
+
+    fn parse_varuint1<R: Read>(f: &mut R) -> Result<u8, ParseError> {
+        Section::parse_signed(f).map(|val| val as u8)
+    }
+}
+
+impl Section {
+    fn id(&self) -> u32 {
+        match self {
+            Section::Custom { .. } => 0,
+            Section::Type { .. } => 1,
+            Section::Import { .. } => 2,
+            Section::Function { .. } => 3,
+            Section::Table { .. } => 4,
+            Section::Memory { .. } => 5,
+            Section::Global { .. } => 6,
+            Section::Export { .. } => 7,
+            Section::Start { .. } => 8,
+            Section::Code { .. } => 10,
+            Section::Unknown { id, .. } => *id,
+        }
+    }
+
+    // Sections are prefixed with their payload size, so the payload is
+    // encoded into a buffer first.
+    fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut payload = vec![];
+        match self {
+            Section::Custom { name, payload: data } => {
+                try!(Section::write_name(&mut payload, name));
+                try!(payload.write_all(data));
+            }
+            Section::Type { entries } => {
+                try!(Section::write_varuint32(&mut payload, entries.len() as u32));
+                for entry in entries {
+                    try!(Section::write_func_type(&mut payload, entry));
+                }
+            }
+            Section::Import { entries } => {
+                try!(Section::write_varuint32(&mut payload, entries.len() as u32));
+                for entry in entries {
+                    try!(Section::write_import_entry(&mut payload, entry));
+                }
+            }
+            Section::Function { types } => {
+                try!(Section::write_varuint32(&mut payload, types.len() as u32));
+                for ty in types {
+                    try!(Section::write_varuint32(&mut payload, *ty));
+                }
+            }
+            Section::Table { entries } => {
+                try!(Section::write_varuint32(&mut payload, entries.len() as u32));
+                for entry in entries {
+                    try!(Section::write_table_type(&mut payload, entry));
+                }
+            }
+            Section::Memory { entries } => {
+                try!(Section::write_varuint32(&mut payload, entries.len() as u32));
+                for entry in entries {
+                    try!(Section::write_resizable_limits(&mut payload, &entry.limits));
+                }
+            }
+            Section::Global { entries } => {
+                try!(Section::write_varuint32(&mut payload, entries.len() as u32));
+                for entry in entries {
+                    try!(Section::write_global_type(&mut payload, &entry.ty));
+                    try!(Section::write_init_expr(&mut payload, &entry.init));
+                }
+            }
+            Section::Export { entries } => {
+                try!(Section::write_varuint32(&mut payload, entries.len() as u32));
+                for entry in entries {
+                    try!(Section::write_name(&mut payload, &entry.field_name));
+                    try!(Section::write_external_kind(&mut payload, entry.kind));
+                    try!(Section::write_varuint32(&mut payload, entry.index));
+                }
+            }
+            Section::Start { index } => {
+                try!(Section::write_varuint32(&mut payload, *index));
+            }
+            Section::Code { bodies } => {
+                try!(Section::write_varuint32(&mut payload, bodies.len() as u32));
+                for body in bodies {
+                    try!(Section::write_function_body(&mut payload, body));
+                }
+            }
+            Section::Unknown { payload: data, .. } => {
+                try!(payload.write_all(data));
+            }
+        }
+        try!(Section::write_varuint32(w, self.id()));
+        try!(Section::write_varuint32(w, payload.len() as u32));
+        w.write_all(&payload)
+    }
+
+    fn write_func_type<W: Write>(w: &mut W, ty: &FuncType) -> io::Result<()> {
+        try!(Section::write_varint7(w, ty.form));
+        try!(Section::write_varuint32(w, ty.param_types.len() as u32));
+        for param_type in &ty.param_types {
+            try!(Section::write_value_type(w, *param_type));
+        }
+        try!(Section::write_varuint32(w, ty.return_types.len() as u32));
+        for return_type in &ty.return_types {
+            try!(Section::write_value_type(w, *return_type));
+        }
+        Ok(())
+    }
+
+    fn write_import_entry<W: Write>(w: &mut W, entry: &ImportEntry) -> io::Result<()> {
+        try!(Section::write_name(w, &entry.module_name));
+        try!(Section::write_name(w, &entry.field_name));
+        try!(Section::write_external_kind(w, entry.kind()));
+        match entry.ty {
+            ImportType::Function(type_idx) => Section::write_varuint32(w, type_idx),
+            ImportType::Table(ref ty) => Section::write_table_type(w, ty),
+            ImportType::Memory(ref ty) => Section::write_resizable_limits(w, &ty.limits),
+            ImportType::Global(ref ty) => Section::write_global_type(w, ty),
+        }
+    }
+
+    fn write_external_kind<W: Write>(w: &mut W, kind: ExternalKind) -> io::Result<()> {
+        let external_kind = match kind {
+            ExternalKind::Function => 0,
+            ExternalKind::Table => 1,
+            ExternalKind::Memory => 2,
+            ExternalKind::Global => 3,
+        };
+        w.write_all(&[external_kind])
+    }
+
+    fn write_function_body<W: Write>(w: &mut W, body: &FunctionBody) -> io::Result<()> {
+        let mut buf = vec![];
+        try!(Section::write_varuint32(&mut buf, body.locals.len() as u32));
+        for local in &body.locals {
+            try!(Section::write_varuint32(&mut buf, local.count));
+            try!(Section::write_value_type(&mut buf, local.ty));
+        }
+        try!(buf.write_all(body.code()));
+        try!(buf.write_all(&[0x0b]));
+        try!(Section::write_varuint32(w, buf.len() as u32));
+        w.write_all(&buf)
+    }
+
+    fn write_table_type<W: Write>(w: &mut W, ty: &TableType) -> io::Result<()> {
+        let element_type = match ty.element_type {
+            ElementType::AnyFunc => -0x10,
+            ElementType::ExternRef => -0x11,
+        };
+        try!(Section::write_varint7(w, element_type));
+        Section::write_resizable_limits(w, &ty.limits)
+    }
+
+    fn write_global_type<W: Write>(w: &mut W, ty: &GlobalType) -> io::Result<()> {
+        try!(Section::write_value_type(w, ty.content_type));
+        Section::write_varuint32(w, ty.mutable as u32)
+    }
+
+    fn write_init_expr<W: Write>(w: &mut W, expr: &InitExpr) -> io::Result<()> {
+        match *expr {
+            InitExpr::I32Const(val) => {
+                try!(w.write_all(&[0x41]));
+                try!(leb128::write::signed(w, val as i64));
+            }
+            InitExpr::I64Const(val) => {
+                try!(w.write_all(&[0x42]));
+                try!(leb128::write::signed(w, val));
+            }
+            InitExpr::F32Const(val) => {
+                try!(w.write_all(&[0x43]));
+                try!(w.write_u32::<LittleEndian>(val));
+            }
+            InitExpr::F64Const(val) => {
+                try!(w.write_all(&[0x44]));
+                try!(w.write_u64::<LittleEndian>(val));
+            }
+            InitExpr::V128Const(val) => {
+                try!(w.write_all(&[0xfd]));
+                try!(Section::write_varuint32(w, 0x0c));
+                try!(w.write_all(&val));
+            }
+            InitExpr::GetGlobal(idx) => {
+                try!(w.write_all(&[0x23]));
+                try!(Section::write_varuint32(w, idx));
+            }
+            InitExpr::RefNull(ty) => {
+                try!(w.write_all(&[0xd0]));
+                try!(Section::write_value_type(w, ty));
+            }
+            InitExpr::RefFunc(idx) => {
+                try!(w.write_all(&[0xd2]));
+                try!(Section::write_varuint32(w, idx));
+            }
+        }
+        w.write_all(&[0x0b])
+    }
+
+    fn write_resizable_limits<W: Write>(w: &mut W, limits: &ResizableLimits) -> io::Result<()> {
+        let mut flags = 0;
+        if limits.maximum.is_some() {
+            flags |= 0x1;
+        }
+        if limits.shared {
+            flags |= 0x2;
+        }
+        try!(Section::write_varuint32(w, flags));
+        try!(Section::write_varuint32(w, limits.initial));
+        match limits.maximum {
+            Some(maximum) => Section::write_varuint32(w, maximum),
+            None => Ok(()),
+        }
+    }
+
+    fn write_value_type<W: Write>(w: &mut W, ty: ValueType) -> io::Result<()> {
+        let ty = match ty {
+            ValueType::I32 => -0x01,
+            ValueType::I64 => -0x02,
+            ValueType::F32 => -0x03,
+            ValueType::F64 => -0x04,
+            ValueType::V128 => -0x05,
+            ValueType::FuncRef => -0x10,
+            ValueType::ExternRef => -0x11,
+        };
+        Section::write_varint7(w, ty)
+    }
+
+    fn write_name<W: Write>(w: &mut W, name: &str) -> io::Result<()> {
+        try!(Section::write_varuint32(w, name.len() as u32));
+        w.write_all(name.as_bytes())
+    }
+
+    fn write_varuint32<W: Write>(w: &mut W, val: u32) -> io::Result<()> {
+        leb128::write::unsigned(w, val as u64).map(|_| ())
+    }
+
+    fn write_varint7<W: Write>(w: &mut W, val: i8) -> io::Result<()> {
+        leb128::write::signed(w, val as i64).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One entry in every section the parser understands.
+    #[rustfmt::skip]
+    const ALL_SECTIONS: &[u8] = &[
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+        // (type (func (param i32) (result i32))) (type (func))
+        0x01, 0x09, 0x02, 0x60, 0x01, 0x7f, 0x01, 0x7f, 0x60, 0x00, 0x00,
+        // (import "env" "f" (func (type 0)))
+        0x02, 0x09, 0x01, 0x03, 0x65, 0x6e, 0x76, 0x01, 0x66, 0x00, 0x00,
+        // (func (type 1))
+        0x03, 0x02, 0x01, 0x01,
+        // (table 1 2 anyfunc)
+        0x04, 0x05, 0x01, 0x70, 0x01, 0x01, 0x02,
+        // (memory 1)
+        0x05, 0x03, 0x01, 0x00, 0x01,
+        // (global (mut i32) (i32.const -1))
+        0x06, 0x06, 0x01, 0x7f, 0x01, 0x41, 0x7f, 0x0b,
+        // (export "main" (func 1)) (export "g" (global 0))
+        0x07, 0x0c, 0x02, 0x04, 0x6d, 0x61, 0x69, 0x6e, 0x00, 0x01, 0x01, 0x67, 0x03, 0x00,
+        // (start 1)
+        0x08, 0x01, 0x01,
+        // return
+        0x0a, 0x05, 0x01, 0x03, 0x00, 0x0f, 0x0b,
+        // custom section "meta"
+        0x00, 0x08, 0x04, 0x6d, 0x65, 0x74, 0x61, 0x01, 0x02, 0x03,
+    ];
+
+    fn roundtrip(bytes: &[u8]) -> Vec<u8> {
+        let module = Module::parse_bytes(bytes).unwrap();
+        let mut out = vec![];
+        module.write(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn write_roundtrip() {
+        assert_eq!(roundtrip(ALL_SECTIONS), ALL_SECTIONS);
+        let add = include_bytes!("../test/add.wasm");
+        assert_eq!(roundtrip(add), &add[..]);
+        let empty = include_bytes!("../test/empty_module.wasm");
+        assert_eq!(roundtrip(empty), &empty[..]);
+        let start = include_bytes!("../test/start.wasm");
+        assert_eq!(roundtrip(start), &start[..]);
+    }
+
+    #[test]
+    fn func_type_display() {
+        let ty = FuncType::new(vec![ValueType::I32, ValueType::F64], vec![ValueType::I64]);
+        assert_eq!(ty.to_string(), "(i32, f64) -> i64");
+        assert_eq!(FuncType::new(vec![], vec![]).to_string(), "()");
+        let ty = FuncType::new(vec![], vec![ValueType::I32, ValueType::V128]);
+        assert_eq!(ty.to_string(), "() -> (i32, v128)");
+    }
+
+    fn unsigned(bytes: &[u8]) -> Result<u64, ParseError> {
+        Section::parse_unsigned(&mut &bytes[..])
+    }
+
+    fn signed(bytes: &[u8]) -> Result<i64, ParseError> {
+        Section::parse_signed(&mut &bytes[..])
+    }
+
+    #[test]
+    fn leb128_unsigned() {
+        assert_eq!(unsigned(&[0x00]).unwrap(), 0);
+        assert_eq!(unsigned(&[0x3f]).unwrap(), 63);
+        // Bit 6 is a value bit, not a sign bit.
+        assert_eq!(unsigned(&[0x40]).unwrap(), 64);
+        assert_eq!(unsigned(&[0x7f]).unwrap(), 127);
+        assert_eq!(unsigned(&[0x80, 0x01]).unwrap(), 128);
+        assert_eq!(unsigned(&[0xe5, 0x8e, 0x26]).unwrap(), 624485);
+        // Overlong but in range.
+        assert_eq!(unsigned(&[0x80, 0x80, 0x00]).unwrap(), 0);
+    }
+
+    #[test]
+    fn leb128_signed() {
+        assert_eq!(signed(&[0x00]).unwrap(), 0);
+        assert_eq!(signed(&[0x3f]).unwrap(), 63);
+        assert_eq!(signed(&[0x40]).unwrap(), -64);
+        assert_eq!(signed(&[0x7f]).unwrap(), -1);
+        assert_eq!(signed(&[0xc0, 0x00]).unwrap(), 64);
+        assert_eq!(signed(&[0x80, 0x7f]).unwrap(), -128);
+        assert_eq!(signed(&[0xc0, 0xbb, 0x78]).unwrap(), -123456);
+    }
+
+    #[test]
+    fn leb128_matches_encoder() {
+        let values = [
+            0,
+            1,
+            63,
+            64,
+            127,
+            128,
+            8191,
+            8192,
+            1 << 31,
+            1 << 32,
+            u64::max_value(),
+        ];
+        for &val in &values {
+            let mut buf = vec![];
+            leb128::write::unsigned(&mut buf, val).unwrap();
+            assert_eq!(unsigned(&buf).unwrap(), val);
+            for &val in &[val as i64, -(val as i64)] {
+                let mut buf = vec![];
+                leb128::write::signed(&mut buf, val).unwrap();
+                assert_eq!(signed(&buf).unwrap(), val);
+            }
+        }
+    }
+
+    #[test]
+    fn leb128_overflow() {
+        let overlong = [
+            0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00,
+        ];
+        match unsigned(&overlong) {
+            Err(ParseError::DecodeError(leb128::read::Error::Overflow)) => (),
+            result => panic!("unexpected result {:?}", result),
+        }
+        match signed(&overlong) {
+            Err(ParseError::DecodeError(leb128::read::Error::Overflow)) => (),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn leb128_truncated() {
+        match unsigned(&[0x80]) {
+            Err(ParseError::IoError(ref e)) if e.kind() == io::ErrorKind::UnexpectedEof => (),
+            result => panic!("unexpected result {:?}", result),
+        }
+        match signed(&[]) {
+            Err(ParseError::IoError(ref e)) if e.kind() == io::ErrorKind::UnexpectedEof => (),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn required_features_from_types() {
+        let header = [0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+        let features = |sections: &[u8]| {
+            let mut bytes = header.to_vec();
+            bytes.extend_from_slice(sections);
+            Module::parse_bytes(&bytes).unwrap().required_features()
+        };
+        // (type (func (result i32 i32)))
+        let multi_value = [0x01, 0x06, 0x01, 0x60, 0x00, 0x02, 0x7f, 0x7f];
+        assert_eq!(features(&multi_value), ["multi-value"]);
+        // (type (func (param v128)))
+        let simd = [0x01, 0x05, 0x01, 0x60, 0x01, 0x7b, 0x00];
+        assert_eq!(features(&simd), ["simd"]);
+        // (table 1 externref)
+        let extern_ref = [0x04, 0x04, 0x01, 0x6f, 0x00, 0x01];
+        assert_eq!(features(&extern_ref), ["reference-types"]);
+        assert!(features(&[]).is_empty());
+    }
+
+    #[test]
+    fn export_edits_without_export_section() {
+        let start = include_bytes!("../test/start.wasm");
+        let mut module = Module::parse_bytes(start).unwrap();
+        assert!(!module.remove_export("nope"));
+        assert!(!module.rename_export("nope", "other"));
+        let mut out = vec![];
+        module.write(&mut out).unwrap();
+        assert_eq!(out, &start[..]);
+        assert!(module.add_export("start", ExternalKind::Function, 0));
+        let mut out = vec![];
+        module.write(&mut out).unwrap();
+        let module = Module::parse_bytes(&out).unwrap();
+        assert_eq!(module.find_export_func_index("start"), Some(0));
+    }
+
+    #[test]
+    fn extern_types() {
+        let module = Module::parse_bytes(ALL_SECTIONS).unwrap();
+        let imports = module.import_types();
+        assert_eq!(imports.len(), 1);
+        match imports[0] {
+            ("env", "f", Some(ExternType::Func(ty))) => assert_eq!(ty.to_string(), "(i32) -> i32"),
+            ref other => panic!("unexpected import {:?}", other),
+        }
+        let exports = module.export_types();
+        assert_eq!(exports.len(), 2);
+        match exports[0] {
+            ("main", Some(ExternType::Func(ty))) => assert_eq!(ty.to_string(), "()"),
+            ref other => panic!("unexpected export {:?}", other),
+        }
+        match exports[1] {
+            ("g", Some(ExternType::Global(ty))) => {
+                assert_eq!(ty.content_type(), ValueType::I32);
+                assert!(ty.is_mutable());
+            }
+            ref other => panic!("unexpected export {:?}", other),
+        }
+        match module.get_export_type("main") {
+            Some(ExternType::Func(ty)) => assert_eq!(ty.to_string(), "()"),
+            other => panic!("unexpected export type {:?}", other),
+        }
+        assert!(module.get_export_type("nope").is_none());
+    }
+
+    #[test]
+    fn index_space_lookups() {
+        let module = Module::parse_bytes(ALL_SECTIONS).unwrap();
+        // Function 0 is the import, function 1 is defined in the module.
+        assert_eq!(module.func_type(0).unwrap().to_string(), "(i32) -> i32");
+        assert_eq!(module.func_type(1).unwrap().to_string(), "()");
+        assert!(module.func_type(2).is_none());
+        assert!(module.find_func(0).is_none());
+        assert!(module.find_func(1).is_some());
+        let table = module.table_type(0).unwrap();
+        assert_eq!(table.element_type(), ElementType::AnyFunc);
+        assert_eq!(table.limits().initial(), 1);
+        assert_eq!(table.limits().maximum(), Some(2));
+        assert!(module.table_type(1).is_none());
+        let memory = module.memory_type(0).unwrap();
+        assert_eq!(memory.limits().initial(), 1);
+        assert_eq!(memory.limits().maximum(), None);
+        assert!(module.memory_type(1).is_none());
+        assert_eq!(
+            module.global_type(0).unwrap().content_type(),
+            ValueType::I32
+        );
+        assert!(module.global_type(1).is_none());
+    }
+
+    #[test]
+    fn type_ids_of_duplicate_signatures() {
+        #[rustfmt::skip]
+        let bytes = [
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+            // (type (func (param i32) (result i32))) (type (func))
+            // (type (func (param i32) (result i32))) (type (func))
+            0x01, 0x11, 0x04, 0x60, 0x01, 0x7f, 0x01, 0x7f, 0x60, 0x00, 0x00,
+            0x60, 0x01, 0x7f, 0x01, 0x7f, 0x60, 0x00, 0x00,
+            // (import "env" "f" (func (type 2)))
+            0x02, 0x09, 0x01, 0x03, 0x65, 0x6e, 0x76, 0x01, 0x66, 0x00, 0x02,
+            // (func (type 0)) (func (type 1)) (func (type 3))
+            0x03, 0x04, 0x03, 0x00, 0x01, 0x03,
+            // three empty bodies
+            0x0a, 0x0a, 0x03, 0x02, 0x00, 0x0b, 0x02, 0x00, 0x0b, 0x02, 0x00, 0x0b,
+        ];
+        let module = Module::parse_bytes(&bytes).unwrap();
+        assert_eq!(module.type_id(0), module.type_id(2));
+        assert_eq!(module.type_id(1), module.type_id(3));
+        assert_ne!(module.type_id(0), module.type_id(1));
+        assert_eq!(module.type_id(4), None);
+        // The imported function 0 has the same type as function 1.
+        assert_eq!(module.func_type_id(0), module.func_type_id(1));
+        assert_eq!(module.func_type_id(2), module.func_type_id(3));
+        assert_ne!(module.func_type_id(0), module.func_type_id(2));
+        assert_eq!(module.func_type_id(0), module.type_id(0));
+        assert_eq!(module.func_type_id(4), None);
+    }
+
+    fn parse_sections(sections: &[u8]) -> Result<Module, ParseError> {
+        let mut bytes = vec![0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+        bytes.extend_from_slice(sections);
+        Module::parse_bytes(&bytes)
+    }
+
+    #[test]
+    fn section_payload_bounds() {
+        let eof = |sections: &[u8]| match parse_sections(sections) {
+            Err(ParseError::IoError(ref e)) if e.kind() == io::ErrorKind::UnexpectedEof => (),
+            result => panic!("unexpected result {:?}", result),
+        };
+        // The function type continues past the one byte payload.
+        eof(&[0x01, 0x01, 0x01, 0x60, 0x00, 0x00]);
+        // The payload is longer than the rest of the module.
+        eof(&[0x01, 0x05, 0x01, 0x60, 0x00, 0x00]);
+        // A truncated section id.
+        eof(&[0x80]);
+        match parse_sections(&[0x01, 0x05, 0x01, 0x60, 0x00, 0x00, 0x00]) {
+            Err(ParseError::SectionSizeMismatch(1)) => (),
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert!(parse_sections(&[]).unwrap().sections.is_empty());
+    }
+
+    #[test]
+    fn function_body_code() {
+        let module = Module::parse_bytes(ALL_SECTIONS).unwrap();
+        assert_eq!(module.find_func(1).unwrap().code(), [0x0f]);
+    }
+
+    fn written(module: &Module) -> Vec<u8> {
+        let mut out = vec![];
+        module.write(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn import_renames() {
+        let mut module = Module::parse_bytes(ALL_SECTIONS).unwrap();
+        assert_eq!(module.rename_import_module("nope", "host"), 0);
+        assert!(!module.rename_import("env", "nope", "host", "g"));
+        assert!(!module.rename_import("nope", "f", "host", "g"));
+        assert_eq!(written(&module), ALL_SECTIONS);
+        assert_eq!(module.rename_import_module("env", "host"), 1);
+        let module = Module::parse_bytes(&written(&module)).unwrap();
+        assert_eq!(module.imports()[0].module_name(), "host");
+        assert_eq!(module.imports()[0].field_name(), "f");
+        let mut module = Module::parse_bytes(ALL_SECTIONS).unwrap();
+        assert!(module.rename_import("env", "f", "host", "g"));
+        let module = Module::parse_bytes(&written(&module)).unwrap();
+        match module.import_types()[0] {
+            ("host", "g", Some(ExternType::Func(ty))) => assert_eq!(ty.to_string(), "(i32) -> i32"),
+            ref other => panic!("unexpected import {:?}", other),
+        }
+    }
+
+    #[test]
+    fn add_export_failures() {
+        let mut module = Module::parse_bytes(ALL_SECTIONS).unwrap();
+        // The name is already exported.
+        assert!(!module.add_export("main", ExternalKind::Function, 0));
+        assert!(!module.add_export("g", ExternalKind::Global, 0));
+        // There is no such entity.
+        assert!(!module.add_export("f2", ExternalKind::Function, 2));
+        assert!(!module.add_export("t1", ExternalKind::Table, 1));
+        assert!(!module.add_export("m1", ExternalKind::Memory, 1));
+        assert!(!module.add_export("g1", ExternalKind::Global, 1));
+        assert_eq!(written(&module), ALL_SECTIONS);
+        // The imported function is in the index space too.
+        assert!(module.add_export("f", ExternalKind::Function, 0));
+        let module = Module::parse_bytes(&written(&module)).unwrap();
+        assert_eq!(module.find_export_func_index("f"), Some(0));
+    }
+
+    #[test]
+    fn alias_exports() {
+        let mut module = Module::parse_bytes(ALL_SECTIONS).unwrap();
+        assert!(!module.alias_export("nope", "other"));
+        assert!(!module.alias_export("main", "g"));
+        assert_eq!(written(&module), ALL_SECTIONS);
+        assert!(module.alias_export("main", "_start"));
+        assert!(module.alias_export("g", "g2"));
+        let module = Module::parse_bytes(&written(&module)).unwrap();
+        assert_eq!(module.find_export_func_index("main"), Some(1));
+        assert_eq!(module.find_export_func_index("_start"), Some(1));
+        match module.get_export_type("g2") {
+            Some(ExternType::Global(ty)) => assert!(ty.is_mutable()),
+            other => panic!("unexpected export type {:?}", other),
+        }
+    }
+
+    // ALL_SECTIONS followed by a custom section "x" with payload [9].
+    fn two_custom_sections() -> Vec<u8> {
+        let mut bytes = ALL_SECTIONS.to_vec();
+        bytes.extend_from_slice(&[0x00, 0x03, 0x01, 0x78, 0x09]);
+        bytes
+    }
+
+    #[test]
+    fn custom_sections_in_module_order() {
+        let mut module = Module::parse_bytes(&two_custom_sections()).unwrap();
+        assert_eq!(
+            module.custom_sections(),
+            [("meta", &[1, 2, 3][..]), ("x", &[9][..])]
+        );
+        module.add_custom_section("meta", vec![4]);
+        assert_eq!(
+            module.custom_sections(),
+            [
+                ("meta", &[1, 2, 3][..]),
+                ("x", &[9][..]),
+                ("meta", &[4][..])
+            ]
+        );
+        assert_eq!(module.custom_section("meta"), Some(&[1, 2, 3][..]));
+        assert_eq!(module.custom_section("nope"), None);
+    }
+
+    #[test]
+    fn set_custom_section_in_place() {
+        let bytes = two_custom_sections();
+        let mut module = Module::parse_bytes(&bytes).unwrap();
+        module.set_custom_section("meta", vec![7, 7]);
+        // Only the payload of "meta" changes, and it stays before "x".
+        let meta = ALL_SECTIONS.len() - 10;
+        let mut expected = bytes[..meta].to_vec();
+        expected.extend_from_slice(&[0x00, 0x07, 0x04, 0x6d, 0x65, 0x74, 0x61, 0x07, 0x07]);
+        expected.extend_from_slice(&bytes[ALL_SECTIONS.len()..]);
+        assert_eq!(written(&module), expected);
+    }
+
+    #[test]
+    fn set_custom_section_appends() {
+        let bytes = two_custom_sections();
+        let mut module = Module::parse_bytes(&bytes).unwrap();
+        module.set_custom_section("new", vec![5]);
+        let mut expected = bytes.clone();
+        expected.extend_from_slice(&[0x00, 0x05, 0x03, 0x6e, 0x65, 0x77, 0x05]);
+        assert_eq!(written(&module), expected);
+    }
+
+    #[test]
+    fn remove_custom_section() {
+        let bytes = two_custom_sections();
+        let mut module = Module::parse_bytes(&bytes).unwrap();
+        assert_eq!(module.remove_custom_section("nope"), 0);
+        assert_eq!(written(&module), bytes);
+        module.add_custom_section("meta", vec![4]);
+        assert_eq!(module.remove_custom_section("meta"), 2);
+        assert_eq!(module.custom_sections(), [("x", &[9][..])]);
     }
 }
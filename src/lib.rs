@@ -1,5 +1,7 @@
 extern crate byteorder;
 extern crate leb128;
+#[macro_use]
+extern crate log;
 
 pub mod binary;
 pub mod opcode;
@@ -1 +1,8 @@
 pub const OPC_RETURN: u8 = 0x0f;
+
+pub fn name(opcode: u8) -> Option<&'static str> {
+    match opcode {
+        OPC_RETURN => Some("return"),
+        _ => None,
+    }
+}